@@ -0,0 +1,408 @@
+use crate::commands::translation::autocomplete_language;
+use crate::data::Bridge;
+use crate::database::BridgeInvite;
+use crate::language::Language;
+use crate::Context;
+
+use anyhow::{bail, Context as _, Result};
+use std::collections::BTreeSet;
+use tokio::time::Duration;
+use tracing::debug;
+
+use poise::command;
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildChannel, GuildId, Mention, Timestamp};
+
+use rand::distributions::{Alphanumeric, DistString};
+
+const INVITE_CODE_LENGTH: usize = 12;
+const INVITE_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60); // 1 day
+
+/// Manage bridges between channels
+///
+/// Bridged channels each get the conversation of the other, translated into their language. Channels of different servers are bridged with an invite.
+#[command(
+    prefix_command,
+    slash_command,
+    category = "Bridge",
+    guild_only,
+    subcommands(
+        "bridge_create",
+        "bridge_invite",
+        "bridge_accept",
+        "bridge_language",
+        "bridge_remove",
+        "bridge_list"
+    ),
+    subcommand_required
+)]
+pub async fn bridge(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Bridge the current channel with another one of the server
+///
+/// Messages in each channel are translated and posted in the other, along with their edits and deletions.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "create",
+    category = "Bridge",
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS",
+    required_bot_permissions = "MANAGE_WEBHOOKS",
+    track_edits
+)]
+pub async fn bridge_create(
+    ctx: Context<'_>,
+    #[description = "Language of the current channel"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+    #[description = "Channel to bridge with"]
+    #[channel_types("Text")]
+    channel: GuildChannel,
+    #[description = "Language of the other channel"]
+    #[autocomplete = "autocomplete_language"]
+    channel_language: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let channel_id = ctx.channel_id();
+    let peer_id = channel.id;
+    let (language, peer_language) = (parse(&language)?, parse(&channel_language)?);
+
+    if channel.guild_id != guild_id {
+        bail!("The channel has to be in this server");
+    }
+    if peer_id == channel_id {
+        bail!("A channel can't be bridged with itself");
+    }
+    if language == peer_language {
+        bail!("The languages have to be different");
+    }
+    check_available(ctx, channel_id)?;
+    check_available(ctx, peer_id)?;
+
+    ctx.defer().await?;
+    connect(
+        ctx,
+        (channel_id, guild_id, language),
+        (peer_id, guild_id, peer_language),
+    )
+    .await?;
+    ctx.reply(format!(
+        "Bridged {} in {} with {} in {}",
+        Mention::from(channel_id),
+        language.name,
+        Mention::from(peer_id),
+        peer_language.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Invite another server to bridge with the current channel
+///
+/// Gives a code an admin of the other server can accept in one of its channels within a day.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "invite",
+    category = "Bridge",
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS",
+    required_bot_permissions = "MANAGE_WEBHOOKS",
+    track_edits,
+    ephemeral
+)]
+pub async fn bridge_invite(
+    ctx: Context<'_>,
+    #[description = "Language of the current channel"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let channel_id = ctx.channel_id();
+    let language = parse(&language)?;
+    check_available(ctx, channel_id)?;
+
+    let code = Alphanumeric.sample_string(&mut rand::thread_rng(), INVITE_CODE_LENGTH);
+    ctx.data().database.insert_invite(&BridgeInvite {
+        code: code.clone(),
+        channel_id,
+        guild_id,
+        language: language.code.to_string(),
+        created_by: ctx.author().id,
+        created_at: Timestamp::now(),
+    })?;
+    ctx.reply(format!(
+        "Have an admin of the other server use `/bridge accept {}` in the channel to bridge with {}",
+        code,
+        Mention::from(channel_id)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Accept an invite to bridge with a channel of another server
+///
+/// Bridges the current channel with the channel the invite was created in.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "accept",
+    category = "Bridge",
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS",
+    required_bot_permissions = "MANAGE_WEBHOOKS",
+    track_edits
+)]
+pub async fn bridge_accept(
+    ctx: Context<'_>,
+    #[description = "Code of the invite"] code: String,
+    #[description = "Language of the current channel"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let channel_id = ctx.channel_id();
+    let language = parse(&language)?;
+    let data = ctx.data();
+
+    let cutoff = Timestamp::now().unix_timestamp() - INVITE_EXPIRY.as_secs() as i64;
+    let invite = data
+        .database
+        .invite(
+            code.trim(),
+            Timestamp::from_unix_timestamp(cutoff).unwrap_or_default(),
+        )?
+        .context("The invite doesn't exist or expired")?;
+    let peer_language = parse(&invite.language)?;
+
+    if invite.channel_id == channel_id {
+        bail!("A channel can't be bridged with itself");
+    }
+    if language == peer_language {
+        bail!("The languages have to be different");
+    }
+    check_available(ctx, channel_id)?;
+    check_available(ctx, invite.channel_id)?;
+
+    ctx.defer().await?;
+    connect(
+        ctx,
+        (channel_id, guild_id, language),
+        (invite.channel_id, invite.guild_id, peer_language),
+    )
+    .await?;
+    data.database.delete_invite(&invite.code)?;
+
+    let guild_name = ctx
+        .guild()
+        .map(|guild| guild.name.clone())
+        .unwrap_or_default();
+    if let Err(err) = invite
+        .channel_id
+        .say(
+            ctx,
+            format!(
+                "{} accepted the invite of {}, this channel is now bridged with #{} of {}",
+                ctx.author().name,
+                Mention::from(invite.created_by),
+                channel_id
+                    .name(ctx)
+                    .await
+                    .unwrap_or_else(|_| channel_id.to_string()),
+                guild_name
+            ),
+        )
+        .await
+    {
+        debug!(
+            "Failed to announce the bridge in {}: {}",
+            invite.channel_id, err
+        );
+    }
+    ctx.reply(format!(
+        "Bridged {} in {} with a channel in {}",
+        Mention::from(channel_id),
+        language.name,
+        peer_language.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Change the language of the current channel's bridge
+///
+/// Messages in the current channel are translated from this language, and the other channel's into it.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "language",
+    category = "Bridge",
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn bridge_language(
+    ctx: Context<'_>,
+    #[description = "Language of the current channel"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+) -> Result<()> {
+    let channel_id = ctx.channel_id();
+    let language = parse(&language)?;
+    let data = ctx.data();
+
+    let (_, peer_language) = data
+        .bridge_peer(channel_id)
+        .context("This channel isn't bridged")?;
+    if language == peer_language {
+        bail!("The languages have to be different");
+    }
+    data.set_bridge_language(channel_id, language)?;
+    ctx.reply(format!(
+        "Messages in {} are translated from and into {}",
+        Mention::from(channel_id),
+        language.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Remove the bridge of the current channel
+///
+/// Stops translating messages between the current channel and the other end of its bridge.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "remove",
+    category = "Bridge",
+    guild_only,
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn bridge_remove(ctx: Context<'_>) -> Result<()> {
+    let channel_id = ctx.channel_id();
+
+    let Some(peer_id) = ctx.data().remove_bridge(channel_id)? else {
+        bail!("This channel isn't bridged");
+    };
+    ctx.reply(format!(
+        "Removed the bridge between {} and {}",
+        Mention::from(channel_id),
+        Mention::from(peer_id)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// List bridges
+///
+/// Lists the bridges of the server's channels.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "list",
+    category = "Bridge",
+    guild_only,
+    track_edits,
+    ephemeral
+)]
+pub async fn bridge_list(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let data = ctx.data();
+
+    // Each bridge once, however many of its ends are in the server
+    let pairs = data
+        .bridges
+        .iter()
+        .filter(|bridge| bridge.guild_id == Some(guild_id))
+        .map(|bridge| {
+            let channel_id = *bridge.key();
+            (
+                channel_id.min(bridge.peer_id),
+                channel_id.max(bridge.peer_id),
+            )
+        })
+        .collect::<BTreeSet<_>>();
+    let lines = pairs
+        .into_iter()
+        .filter_map(|(channel_id, peer_id)| {
+            let (_, peer_language) = data.bridge_peer(channel_id)?;
+            let (_, language) = data.bridge_peer(peer_id)?;
+            Some(format!(
+                "{} {} ↔ {} {}",
+                Mention::from(channel_id),
+                language.flag,
+                Mention::from(peer_id),
+                peer_language.flag
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    match lines.is_empty() {
+        true => ctx.reply("There are no bridges").await?,
+        false => ctx.reply(lines.join("\n")).await?,
+    };
+
+    Ok(())
+}
+
+/// Bridges two channels, creating a webhook in each to post the other's translations
+async fn connect(
+    ctx: Context<'_>,
+    (channel_id, guild_id, language): (ChannelId, GuildId, &'static Language),
+    (peer_id, peer_guild_id, peer_language): (ChannelId, GuildId, &'static Language),
+) -> Result<()> {
+    let data = ctx.data();
+    let webhook = data.webhook(ctx.serenity_context(), channel_id).await?;
+    let peer_webhook = data.webhook(ctx.serenity_context(), peer_id).await?;
+
+    data.create_bridge([
+        (
+            channel_id,
+            Bridge {
+                guild_id: Some(guild_id),
+                peer_id,
+                language,
+                webhook,
+            },
+        ),
+        (
+            peer_id,
+            Bridge {
+                guild_id: Some(peer_guild_id),
+                peer_id: channel_id,
+                language: peer_language,
+                webhook: peer_webhook,
+            },
+        ),
+    ])
+}
+
+fn parse(code: &str) -> Result<&'static Language> {
+    Language::from_code(code).with_context(|| format!("Unknown language `{}`", code))
+}
+
+/// Fails if `channel_id` is already bridged or translated in place
+fn check_available(ctx: Context<'_>, channel_id: ChannelId) -> Result<()> {
+    let data = ctx.data();
+
+    if data.bridges.contains_key(&channel_id) {
+        bail!("{} is already bridged", Mention::from(channel_id));
+    }
+    if data.autotranslate_channels.contains_key(&channel_id) {
+        bail!(
+            "Translation is enabled in {}, disable it first",
+            Mention::from(channel_id)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use crate::database::StoredGlossary;
+use crate::language::{self, Language};
+use crate::Context;
+
+use anyhow::{bail, Context as _, Result};
+
+use poise::command;
+use poise::serenity_prelude as serenity;
+use serenity::GuildId;
+
+const MAX_LISTED_ENTRIES: usize = 50;
+
+/// Manage the server's glossaries
+///
+/// Glossaries make sure terms are always translated the same way.
+#[command(
+    prefix_command,
+    slash_command,
+    category = "Glossary",
+    guild_only,
+    subcommands(
+        "glossary_create",
+        "glossary_list",
+        "glossary_add",
+        "glossary_remove",
+        "glossary_delete"
+    ),
+    subcommand_required
+)]
+pub async fn glossary(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Create a glossary
+///
+/// Creates an empty glossary for translations from one language to another.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "create",
+    category = "Glossary",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn glossary_create(
+    ctx: Context<'_>,
+    #[description = "Source language code, e.g. en"] from: String,
+    #[description = "Target language code, e.g. ja"] to: String,
+    #[description = "Name of the glossary"] name: Option<String>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let (source, target) = pair(&from, &to)?;
+    language::check_supported(ctx.data().translator.as_ref(), &[source, target]).await?;
+    let database = &ctx.data().database;
+
+    if database
+        .glossary(guild_id, source.code, target.code)?
+        .is_some()
+    {
+        bail!(
+            "A glossary from {} to {} already exists",
+            source.name,
+            target.name
+        );
+    }
+
+    database.insert_glossary(&StoredGlossary {
+        guild_id,
+        source_lang: source.code.to_string(),
+        target_lang: target.code.to_string(),
+        name: name.unwrap_or_else(|| format!("{}-{}-{}", guild_id, source.code, target.code)),
+        glossary_id: None,
+    })?;
+    ctx.reply(format!(
+        "Created a glossary from {} to {}",
+        source.name, target.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// List glossaries
+///
+/// Lists the server's glossaries, or the entries of one if the languages are provided.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "list",
+    category = "Glossary",
+    guild_only,
+    track_edits,
+    ephemeral
+)]
+pub async fn glossary_list(
+    ctx: Context<'_>,
+    #[description = "Source language code, e.g. en"] from: Option<String>,
+    #[description = "Target language code, e.g. ja"] to: Option<String>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let database = &ctx.data().database;
+
+    let (Some(from), Some(to)) = (from, to) else {
+        let glossaries = database
+            .glossaries(guild_id)?
+            .into_iter()
+            .map(|glossary| {
+                let entries = database
+                    .glossary_entries(guild_id, &glossary.source_lang, &glossary.target_lang)?
+                    .len();
+                Ok(format!(
+                    "`{} → {}` {} ({} entries)",
+                    glossary.source_lang, glossary.target_lang, glossary.name, entries
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match glossaries.is_empty() {
+            true => ctx.reply("There are no glossaries").await?,
+            false => ctx.reply(glossaries.join("\n")).await?,
+        };
+        return Ok(());
+    };
+
+    let (source, target) = pair(&from, &to)?;
+    let entries = database.glossary_entries(guild_id, source.code, target.code)?;
+    if entries.is_empty() {
+        ctx.reply(format!(
+            "The glossary from {} to {} has no entries",
+            source.name, target.name
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = entries
+        .iter()
+        .take(MAX_LISTED_ENTRIES)
+        .map(|(source_term, target_term)| format!("`{}` → `{}`", source_term, target_term))
+        .collect::<Vec<_>>();
+    if entries.len() > MAX_LISTED_ENTRIES {
+        lines.push(format!("and {} more", entries.len() - MAX_LISTED_ENTRIES));
+    }
+    ctx.reply(lines.join("\n")).await?;
+
+    Ok(())
+}
+
+/// Add an entry to a glossary
+///
+/// Adds or replaces how a term is translated.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "add",
+    category = "Glossary",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn glossary_add(
+    ctx: Context<'_>,
+    #[description = "Source language code, e.g. en"] from: String,
+    #[description = "Target language code, e.g. ja"] to: String,
+    #[description = "Term in the source language"] term: String,
+    #[description = "Translation of the term"] translation: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let (source, target) = pair(&from, &to)?;
+    let (term, translation) = (entry_term(&term)?, entry_term(&translation)?);
+    let glossary = find(ctx, guild_id, source, target)?;
+
+    ctx.defer().await?;
+    let database = &ctx.data().database;
+    let mut entries = database.glossary_entries(guild_id, source.code, target.code)?;
+    match entries
+        .iter_mut()
+        .find(|(source_term, _)| source_term == term)
+    {
+        Some((_, target_term)) => *target_term = translation.to_string(),
+        None => entries.push((term.to_string(), translation.to_string())),
+    }
+    sync(ctx, glossary, &entries).await?;
+    database.insert_glossary_entry(guild_id, source.code, target.code, term, translation)?;
+    ctx.reply(format!("`{}` is now translated as `{}`", term, translation))
+        .await?;
+
+    Ok(())
+}
+
+/// Remove an entry from a glossary
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "remove",
+    category = "Glossary",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn glossary_remove(
+    ctx: Context<'_>,
+    #[description = "Source language code, e.g. en"] from: String,
+    #[description = "Target language code, e.g. ja"] to: String,
+    #[description = "Term in the source language"] term: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let (source, target) = pair(&from, &to)?;
+    let term = entry_term(&term)?;
+    let glossary = find(ctx, guild_id, source, target)?;
+
+    ctx.defer().await?;
+    let database = &ctx.data().database;
+    let mut entries = database.glossary_entries(guild_id, source.code, target.code)?;
+    let Some(index) = entries
+        .iter()
+        .position(|(source_term, _)| source_term == term)
+    else {
+        bail!("`{}` isn't in the glossary", term);
+    };
+    entries.remove(index);
+    sync(ctx, glossary, &entries).await?;
+    database.delete_glossary_entry(guild_id, source.code, target.code, term)?;
+    ctx.reply(format!("Removed `{}` from the glossary", term))
+        .await?;
+
+    Ok(())
+}
+
+/// Delete a glossary
+///
+/// Deletes a glossary and all of its entries.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "delete",
+    category = "Glossary",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn glossary_delete(
+    ctx: Context<'_>,
+    #[description = "Source language code, e.g. en"] from: String,
+    #[description = "Target language code, e.g. ja"] to: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let (source, target) = pair(&from, &to)?;
+    let glossary = find(ctx, guild_id, source, target)?;
+
+    if let Some(id) = &glossary.glossary_id {
+        ctx.data().translator.delete_glossary(id).await?;
+    }
+    ctx.data()
+        .database
+        .delete_glossary(guild_id, source.code, target.code)?;
+    ctx.reply(format!(
+        "Deleted the glossary from {} to {}",
+        source.name, target.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+fn pair(from: &str, to: &str) -> Result<(&'static Language, &'static Language)> {
+    let source =
+        Language::from_code(from).with_context(|| format!("Unknown language `{}`", from))?;
+    let target = Language::from_code(to).with_context(|| format!("Unknown language `{}`", to))?;
+
+    if source == target {
+        bail!("The languages have to be different");
+    }
+
+    Ok((source, target))
+}
+
+fn entry_term(term: &str) -> Result<&str> {
+    let term = term.trim();
+
+    if term.is_empty() || term.contains(['\t', '\n', '\r']) {
+        bail!("Terms can't be empty or contain tabs and new lines");
+    }
+
+    Ok(term)
+}
+
+fn find(
+    ctx: Context<'_>,
+    guild_id: GuildId,
+    source: &Language,
+    target: &Language,
+) -> Result<StoredGlossary> {
+    ctx.data()
+        .database
+        .glossary(guild_id, source.code, target.code)?
+        .with_context(|| {
+            format!(
+                "There is no glossary from {} to {}, create it first",
+                source.name, target.name
+            )
+        })
+}
+
+/// Replaces the translator's copy of `glossary` with `entries`
+///
+/// Glossaries can't be edited, so a new one is created before deleting the old one. The entries
+/// are stored by the caller only once this succeeded, so they always match the translator's.
+async fn sync(
+    ctx: Context<'_>,
+    mut glossary: StoredGlossary,
+    entries: &[(String, String)],
+) -> Result<()> {
+    let data = ctx.data();
+    let (source, target) = (&glossary.source_lang, &glossary.target_lang);
+
+    let previous_id = glossary.glossary_id.take();
+    if !entries.is_empty() {
+        let id = data
+            .translator
+            .create_glossary(&glossary.name, source, target, entries)
+            .await?;
+        glossary.glossary_id = Some(id);
+    }
+    data.database.insert_glossary(&glossary)?;
+
+    if let Some(id) = previous_id {
+        data.translator.delete_glossary(&id).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::commands::translation::autocomplete_language;
+use crate::database::{Delivery, Preference};
+use crate::language::Language;
+use crate::Context;
+
+use anyhow::{Context as _, Result};
+
+use poise::command;
+
+/// Manage your own language
+///
+/// Messages you translate with the context menu or reactions are sent only to you, in your language.
+#[command(
+    prefix_command,
+    slash_command,
+    category = "Translation",
+    subcommands("language_set", "language_clear"),
+    subcommand_required
+)]
+pub async fn language(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Set your language
+///
+/// Translations you ask for are sent only to you in this language. (default `only me`)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "set",
+    category = "Translation",
+    track_edits,
+    ephemeral
+)]
+pub async fn language_set(
+    ctx: Context<'_>,
+    #[description = "Language to translate to"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+    #[description = "How to receive translations"] delivery: Option<Delivery>,
+) -> Result<()> {
+    let language = Language::from_code(&language)
+        .with_context(|| format!("Unknown language `{}`", language))?;
+    let preference = Preference {
+        language,
+        delivery: delivery.unwrap_or(Delivery::Ephemeral),
+    };
+
+    ctx.data()
+        .database
+        .set_preference(ctx.author().id, Some(&preference))?;
+    ctx.reply(match preference.delivery {
+        Delivery::Ephemeral => format!(
+            "Translations will be shown only to you in {}",
+            language.name
+        ),
+        Delivery::DirectMessage => {
+            format!("Translations will be sent to your DMs in {}", language.name)
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Clear your language
+///
+/// Translations you ask for are posted in the channel again.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "clear",
+    category = "Translation",
+    track_edits,
+    ephemeral
+)]
+pub async fn language_clear(ctx: Context<'_>) -> Result<()> {
+    ctx.data().database.set_preference(ctx.author().id, None)?;
+    ctx.reply("Translations will be posted in the channel")
+        .await?;
+
+    Ok(())
+}
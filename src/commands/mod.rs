@@ -1,3 +1,6 @@
+pub mod bridge;
+pub mod glossary;
+pub mod language;
 pub mod other;
 pub mod owner;
 pub mod translation;
@@ -1,4 +1,4 @@
-use crate::{Context, TranslateHook};
+use crate::Context;
 
 use anyhow::Result;
 
@@ -45,7 +45,9 @@ pub async fn cleanup(
     amount: Option<usize>,
 ) -> Result<()> {
     let amount = amount.unwrap_or(1);
-    let webhook_user_id: u64 = TranslateHook::new(ctx.serenity_context(), &ctx.channel_id())
+    let webhook_user_id: u64 = ctx
+        .data()
+        .webhook(ctx.serenity_context(), ctx.channel_id())
         .await?
         .webhook
         .id
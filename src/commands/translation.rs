@@ -1,64 +1,860 @@
+use crate::database::{Delivery, Preference};
+use crate::language::{self, Language, LANGUAGES};
+use crate::translation::{Origin, MAX_CONTEXT_MESSAGES};
+use crate::translator::{Formality, Options, SplitSentences};
 use crate::{Context, TranslateHook, Translation};
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
+use tokio::time::Duration;
 
-use poise::command;
 use poise::serenity_prelude as serenity;
-use serenity::{Mention, Message};
+use poise::{command, ChoiceParameter, CreateReply};
+use serenity::{
+    AutocompleteChoice, ButtonStyle, ComponentInteraction, ComponentInteractionCollector,
+    ComponentInteractionDataKind, CreateActionRow, CreateButton, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateSelectMenu, CreateSelectMenuKind,
+    CreateSelectMenuOption, GuildId, Mention, Message,
+};
 
 use num_format::{Locale, ToFormattedString};
 
-/// Toggle automatic translation in the current channel
+const COMPONENT_TIMEOUT: Duration = Duration::from_secs(2 * 60); // 2 minutes
+/// Reply to posting the translation of a message whose author opted out
+pub const OPTED_OUT: &str =
+    "The author of this message opted out of having it translated in the channel";
+
+/// Manage automatic translation in the current channel
 ///
 /// When enabled, the bot will automatically translate messages in the channel.
 #[command(
     prefix_command,
     slash_command,
     category = "Translation",
+    subcommands(
+        "autotranslate_enable",
+        "autotranslate_disable",
+        "autotranslate_languages",
+        "autotranslate_confidence",
+        "autotranslate_options",
+        "autotranslate_include",
+        "autotranslate_context",
+        "autotranslate_optout",
+        "autotranslate_optin",
+        "autotranslate_optouts",
+        "autotranslate_reactions"
+    ),
+    subcommand_required
+)]
+pub async fn autotranslate(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Enable automatic translation in the current channel
+///
+/// Translates between the provided comma separated languages. (default `en,ja`)
+/// Enabling it again keeps the channel's settings and replaces its webhook.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "enable",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
     required_bot_permissions = "MANAGE_WEBHOOKS",
     track_edits
 )]
-pub async fn autotranslate(ctx: Context<'_>) -> Result<()> {
+pub async fn autotranslate_enable(
+    ctx: Context<'_>,
+    #[description = "Comma separated language codes, e.g. en,ja,ko"] languages: Option<String>,
+) -> Result<()> {
     let channel_id = ctx.channel_id();
-    let mention = Mention::from(channel_id);
+    if ctx.data().bridges.contains_key(&channel_id) {
+        bail!("This channel is bridged, remove the bridge first");
+    }
+    let enabled = ctx
+        .data()
+        .autotranslate_channels
+        .get(&channel_id)
+        .map(|channel| channel.settings.clone());
+    let already_enabled = enabled.is_some();
+    let mut settings = enabled.unwrap_or_else(|| ctx.data().default_settings.clone());
+    if let Some(languages) = languages {
+        settings.languages = language::parse_list(&languages)?;
+        language::check_supported(ctx.data().translator.as_ref(), &settings.languages).await?;
+    }
+    let list = language::describe_list(&settings.languages);
 
-    let channels = &ctx.data().autotranslate_channels;
-    let response = |status| format!("Translation {} in {}", status, mention);
+    let webhook = ctx
+        .data()
+        .webhook(ctx.serenity_context(), channel_id)
+        .await?;
+    ctx.data().enable_autotranslate(
+        channel_id,
+        ctx.guild_id(),
+        ctx.author().id,
+        webhook,
+        settings,
+    )?;
+    let reply = match already_enabled {
+        true => format!(
+            "Translation was already enabled in {} between {}, its webhook was replaced",
+            Mention::from(channel_id),
+            list
+        ),
+        false => format!(
+            "Translation enabled in {} between {}",
+            Mention::from(channel_id),
+            list
+        ),
+    };
+    ctx.reply(reply).await?;
+
+    Ok(())
+}
 
-    if channels.contains_key(&channel_id) {
-        channels.remove(&channel_id);
-        ctx.reply(response("disabled")).await?;
-    } else {
-        let webhook = TranslateHook::new(ctx.serenity_context(), &channel_id).await?;
-        ctx.data().enable_autotranslate(channel_id, webhook);
-        ctx.reply(response("enabled")).await?;
+/// Disable automatic translation in the current channel
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "disable",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_disable(ctx: Context<'_>) -> Result<()> {
+    let channel_id = ctx.channel_id();
+
+    if !ctx.data().autotranslate_channels.contains_key(&channel_id) {
+        ctx.reply("Translation isn't enabled in this channel")
+            .await?;
+        return Ok(());
     }
 
+    ctx.data().disable_autotranslate(channel_id)?;
+    ctx.reply(format!(
+        "Translation disabled in {}",
+        Mention::from(channel_id)
+    ))
+    .await?;
+
     Ok(())
 }
 
+/// Change the languages of the current channel
+///
+/// Sets the comma separated languages messages are translated between.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "languages",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_languages(
+    ctx: Context<'_>,
+    #[description = "Comma separated language codes, e.g. en,ja,ko"] languages: String,
+) -> Result<()> {
+    let languages = language::parse_list(&languages)?;
+    language::check_supported(ctx.data().translator.as_ref(), &languages).await?;
+    let list = language::describe_list(&languages);
+
+    ctx.data()
+        .update_settings(ctx.channel_id(), |settings| settings.languages = languages)?;
+    ctx.reply(format!("Translating between {}", list)).await?;
+
+    Ok(())
+}
+
+/// Change the language detection threshold of the current channel
+///
+/// Messages whose language is detected with a lower confidence are not translated. (default `0`)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "confidence",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_confidence(
+    ctx: Context<'_>,
+    #[description = "Minimum confidence between 0 and 1"]
+    #[min = 0]
+    #[max = 1]
+    confidence: f64,
+) -> Result<()> {
+    let confidence = confidence.clamp(0.0, 1.0);
+
+    ctx.data().update_settings(ctx.channel_id(), |settings| {
+        settings.min_confidence = confidence
+    })?;
+    ctx.reply(format!(
+        "Minimum detection confidence set to `{:.2}`",
+        confidence
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Change the translation options of the current channel
+///
+/// Sets how messages in the channel are translated, unless their author overrides it with `/preferences`.
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "options",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_options(
+    ctx: Context<'_>,
+    #[description = "How formal translations are"] formality: Option<Formality>,
+    #[description = "Where text is split into sentences"] split_sentences: Option<SplitSentences>,
+    #[description = "Keep punctuation and casing as written"] preserve_formatting: Option<bool>,
+    #[description = "Unset all options first"] reset: Option<bool>,
+) -> Result<()> {
+    let mut options = Options::default();
+    ctx.data().update_settings(ctx.channel_id(), |settings| {
+        let previous = match reset.unwrap_or_default() {
+            true => Options::default(),
+            false => settings.options,
+        };
+        settings.options = Options {
+            formality,
+            split_sentences,
+            preserve_formatting,
+        }
+        .or(previous);
+        options = settings.options;
+    })?;
+    ctx.reply(options.to_string()).await?;
+
+    Ok(())
+}
+
+/// Choose what else is translated in the current channel
+///
+/// Sets whether embeds posted by bots and small text attachments are translated along with messages. (default off)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "include",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_include(
+    ctx: Context<'_>,
+    #[description = "Translate embeds, e.g. from feeds and announcements"] embeds: Option<bool>,
+    #[description = "Translate .txt and .md attachments"] attachments: Option<bool>,
+) -> Result<()> {
+    let mut included = (false, false);
+    ctx.data().update_settings(ctx.channel_id(), |settings| {
+        settings.translate_embeds = embeds.unwrap_or(settings.translate_embeds);
+        settings.translate_attachments = attachments.unwrap_or(settings.translate_attachments);
+        included = (settings.translate_embeds, settings.translate_attachments);
+    })?;
+
+    let state = |enabled| if enabled { "on" } else { "off" };
+    ctx.reply(format!(
+        "Embeds `{}`, attachments `{}`",
+        state(included.0),
+        state(included.1)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Change how many messages are used as context in the current channel
+///
+/// Short messages are translated along with the message they reply to and this many previous messages, which aren't billed. (default `0`)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "context",
+    category = "Translation",
+    required_permissions = "MANAGE_CHANNELS",
+    track_edits
+)]
+pub async fn autotranslate_context(
+    ctx: Context<'_>,
+    #[description = "Number of previous messages, 0 to disable"]
+    #[max = 10]
+    messages: u8,
+) -> Result<()> {
+    let messages = messages.min(MAX_CONTEXT_MESSAGES);
+
+    ctx.data().update_settings(ctx.channel_id(), |settings| {
+        settings.context_messages = messages
+    })?;
+    match messages {
+        0 => ctx.reply("Messages are translated without context").await?,
+        messages => {
+            ctx.reply(format!(
+                "Messages are translated with the previous `{}` as context",
+                messages
+            ))
+            .await?
+        }
+    };
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, ChoiceParameter)]
+enum Scope {
+    #[name = "this server"]
+    Server,
+    #[name = "everywhere"]
+    Everywhere,
+}
+
+impl Scope {
+    /// The server the scope covers, `None` for every server
+    fn guild_id(self, ctx: Context<'_>) -> Result<Option<GuildId>> {
+        match self {
+            Self::Server => Ok(Some(ctx.guild_id().context("Not in a server")?)),
+            Self::Everywhere => Ok(None),
+        }
+    }
+
+    /// This server if in one, else everywhere
+    fn or_default(scope: Option<Self>, ctx: Context<'_>) -> Self {
+        scope.unwrap_or(match ctx.guild_id() {
+            Some(_) => Self::Server,
+            None => Self::Everywhere,
+        })
+    }
+}
+
+/// Stop translating your messages
+///
+/// Your messages won't be translated and reposted under your name and avatar, if the server allows opting out. (default `this server`)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "optout",
+    category = "Translation",
+    track_edits,
+    ephemeral
+)]
+pub async fn autotranslate_optout(
+    ctx: Context<'_>,
+    #[description = "Where to stop translating your messages"] scope: Option<Scope>,
+) -> Result<()> {
+    let scope = Scope::or_default(scope, ctx);
+    let guild_id = scope.guild_id(ctx)?;
+    let database = &ctx.data().database;
+
+    database.set_opted_out(ctx.author().id, guild_id, true)?;
+    let mut reply = match scope {
+        Scope::Server => "Your messages won't be translated in this server".to_string(),
+        Scope::Everywhere => "Your messages won't be translated anywhere".to_string(),
+    };
+    if let Some(guild_id) = ctx.guild_id() {
+        if !database.allows_opt_out(guild_id)? {
+            reply.push_str(", except in this server as it doesn't allow opting out");
+        }
+    }
+    ctx.reply(reply).await?;
+
+    Ok(())
+}
+
+/// Translate your messages again
+///
+/// Undoes `/autotranslate optout`. (default `this server`)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "optin",
+    category = "Translation",
+    track_edits,
+    ephemeral
+)]
+pub async fn autotranslate_optin(
+    ctx: Context<'_>,
+    #[description = "Where to translate your messages again"] scope: Option<Scope>,
+) -> Result<()> {
+    let scope = Scope::or_default(scope, ctx);
+    let guild_id = scope.guild_id(ctx)?;
+    let database = &ctx.data().database;
+
+    database.set_opted_out(ctx.author().id, guild_id, false)?;
+    let reply = match scope {
+        Scope::Server if database.opted_out(ctx.author().id, None)? => {
+            "You're still opted out everywhere, use `everywhere` to opt back in"
+        }
+        Scope::Server => "Your messages will be translated in this server",
+        Scope::Everywhere => "Your messages will be translated everywhere you haven't opted out",
+    };
+    ctx.reply(reply).await?;
+
+    Ok(())
+}
+
+/// Choose whether members can opt out
+///
+/// Sets whether members can stop their messages from being translated with `/autotranslate optout`. (default on)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "optouts",
+    category = "Translation",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn autotranslate_optouts(
+    ctx: Context<'_>,
+    #[description = "Allow members to opt out"] allowed: bool,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+
+    ctx.data().database.set_allow_opt_out(guild_id, allowed)?;
+    match allowed {
+        true => ctx.reply("Members can opt out of translation").await?,
+        false => {
+            ctx.reply(
+                "Members can't opt out of translation, messages of those who did are translated",
+            )
+            .await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Choose whether flag reactions translate messages
+///
+/// Sets whether reacting to a message with a flag, e.g. 🇫🇷, translates it into that country's language in any channel, or privately into their own language for users who set one. (default off)
+#[command(
+    prefix_command,
+    slash_command,
+    rename = "reactions",
+    category = "Translation",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn autotranslate_reactions(
+    ctx: Context<'_>,
+    #[description = "Translate messages reacted to with a flag"] enabled: bool,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+
+    ctx.data().database.set_flag_reactions(guild_id, enabled)?;
+    match enabled {
+        true => ctx.reply("Flag reactions translate messages").await?,
+        false => ctx.reply("Flag reactions don't translate messages").await?,
+    };
+
+    Ok(())
+}
+
+/// Change your translation preferences
+///
+/// Overrides the options of the channel when translating your messages.
+#[command(
+    prefix_command,
+    slash_command,
+    category = "Translation",
+    track_edits,
+    ephemeral
+)]
+pub async fn preferences(
+    ctx: Context<'_>,
+    #[description = "How formal translations are"] formality: Option<Formality>,
+    #[description = "Where text is split into sentences"] split_sentences: Option<SplitSentences>,
+    #[description = "Keep punctuation and casing as written"] preserve_formatting: Option<bool>,
+    #[description = "Unset all preferences first"] reset: Option<bool>,
+) -> Result<()> {
+    let database = &ctx.data().database;
+    let user_id = ctx.author().id;
+
+    let mut options = match reset.unwrap_or_default() {
+        true => Options::default(),
+        false => database.user_options(user_id)?,
+    };
+    options = Options {
+        formality,
+        split_sentences,
+        preserve_formatting,
+    }
+    .or(options);
+    database.set_user_options(user_id, &options)?;
+    ctx.reply(options.to_string()).await?;
+
+    Ok(())
+}
+
+/// Translate text
+///
+/// Translates the provided text, only visible to you unless `public` is set.
+#[command(slash_command, category = "Translation")]
+pub async fn translate(
+    ctx: Context<'_>,
+    #[description = "Text to translate"] text: String,
+    #[description = "Language to translate to"]
+    #[autocomplete = "autocomplete_language"]
+    to: String,
+    #[description = "Language of the text, detected if missing"]
+    #[autocomplete = "autocomplete_language"]
+    from: Option<String>,
+    #[description = "Show the translation to everyone"] public: Option<bool>,
+) -> Result<()> {
+    let target = Language::from_code(&to).with_context(|| format!("Unknown language `{}`", to))?;
+    let source = match from {
+        Some(from) => Some(
+            Language::from_code(&from).with_context(|| format!("Unknown language `{}`", from))?,
+        ),
+        None => None,
+    };
+
+    let origin = Origin {
+        user_id: ctx.author().id,
+        guild_id: ctx.guild_id(),
+        channel_id: ctx.channel_id(),
+    };
+    let settings = ctx.data().channel_settings(ctx.channel_id());
+    let translation =
+        Translation::from_text(ctx.data(), &text, origin, source, target, &settings).await?;
+
+    let public = public.unwrap_or_default();
+    match public {
+        true => ctx.defer().await?,
+        false => ctx.defer_ephemeral().await?,
+    }
+    let translated = translation.translate(target).await?;
+    ctx.send(
+        CreateReply::default()
+            .content(format!("{} {}", target.flag, translated.content))
+            .ephemeral(!public),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Suggests languages whose code or name starts with `partial`
+pub async fn autocomplete_language(_ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let partial = partial.trim().to_lowercase();
+
+    LANGUAGES
+        .iter()
+        .filter(|language| {
+            language.code.starts_with(&partial)
+                || language.name.to_lowercase().starts_with(&partial)
+        })
+        .map(|language| {
+            AutocompleteChoice::new(
+                format!("{} {}", language.flag, language.name),
+                language.code,
+            )
+        })
+        .collect()
+}
+
 /// Translate a message
 ///
-/// Translates the provided message.
+/// Translates the provided message, only for you if you set your language with `/language set`.
 #[command(context_menu_command = "Translate Message", category = "Translation")]
-pub async fn translate(ctx: Context<'_>, message: Message) -> Result<()> {
+pub async fn translate_message(ctx: Context<'_>, mut message: Message) -> Result<()> {
+    message.guild_id = message.guild_id.or(ctx.guild_id());
+    if let Some(preference) = ctx.data().database.preference(ctx.author().id)? {
+        return translate_privately(ctx, &message, &preference).await;
+    }
+    if ctx.data().opted_out(message.author.id, message.guild_id)? {
+        ctx.send(CreateReply::default().content(OPTED_OUT).ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
     ctx.reply(format!("{}\u{200b}", ctx.data().loading_emoji))
         .await?
         .delete(ctx)
         .await?;
 
-    let translation = Translation::new(&ctx.data().deepl, &message)?;
-    TranslateHook::new(ctx.serenity_context(), &message.channel_id)
-        .await?
-        .translate_reply(&ctx, &ctx.framework(), &message, &translation)
+    let mut settings = ctx.data().channel_settings(message.channel_id);
+    settings.translate_embeds = true;
+    settings.translate_attachments = true;
+    let translation =
+        Translation::new(ctx.serenity_context(), ctx.data(), &message, &settings).await?;
+    let webhook = ctx
+        .data()
+        .webhook(ctx.serenity_context(), message.channel_id)
         .await?;
 
+    let (message, translation) = (&message, &translation);
+    for target in &translation.targets {
+        post_translation(ctx, &webhook, message, translation, target).await?;
+    }
+
+    Ok(())
+}
+
+/// Translates `message` into the user's language, without posting it in the channel
+async fn translate_privately(
+    ctx: Context<'_>,
+    message: &Message,
+    preference: &Preference,
+) -> Result<()> {
+    let target = preference.language;
+    ctx.defer_ephemeral().await?;
+
+    let mut settings = ctx.data().channel_settings(message.channel_id);
+    settings.translate_embeds = true;
+    settings.translate_attachments = true;
+    if !settings.languages.contains(&target) {
+        settings.languages.push(target);
+    }
+    let translation =
+        Translation::new(ctx.serenity_context(), ctx.data(), message, &settings).await?;
+    if translation.detection.language == target {
+        ctx.reply(format!("The message is already in {}", target.name))
+            .await?;
+        return Ok(());
+    }
+
+    let translated = translation.translate(target).await?;
+    match preference.delivery {
+        Delivery::Ephemeral => {
+            ctx.send(CreateReply {
+                content: Some(format!("{} {}", target.flag, translated.content)),
+                embeds: translated.embeds,
+                attachments: translated.attachments,
+                ..Default::default()
+            })
+            .await?;
+        }
+        Delivery::DirectMessage => {
+            ctx.author()
+                .direct_message(ctx, translated.into_message(target))
+                .await?;
+            ctx.reply(format!(
+                "Sent the translation of {} to your DMs",
+                message.link()
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Translate a message to a language of your choice
+///
+/// Lets you pick the language, with the ones you used recently first, and shows the translation only to you until you post it.
+#[command(context_menu_command = "Translate to…", category = "Translation")]
+pub async fn translate_to(ctx: Context<'_>, mut message: Message) -> Result<()> {
+    message.guild_id = message.guild_id.or(ctx.guild_id());
+    let data = ctx.data();
+    let prefix = ctx.id().to_string();
+
+    let recent = data.database.recent_languages(ctx.author().id)?;
+    let languages = recent
+        .iter()
+        .filter_map(|code| Language::from_code(code))
+        .chain(
+            LANGUAGES
+                .iter()
+                .filter(|language| !recent.iter().any(|code| code == language.code)),
+        )
+        .collect::<Vec<_>>();
+
+    // Select menus hold up to 25 options
+    let menus = languages
+        .chunks(25)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let options = chunk
+                .iter()
+                .map(|language| {
+                    CreateSelectMenuOption::new(
+                        format!("{} {}", language.flag, language.name),
+                        language.code,
+                    )
+                })
+                .collect();
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(
+                    format!("{}-language-{}", prefix, index),
+                    CreateSelectMenuKind::String { options },
+                )
+                .placeholder("Translate to…"),
+            )
+        })
+        .collect();
+    let reply = ctx
+        .send(CreateReply::default().components(menus).ephemeral(true))
+        .await?;
+
+    let Some(interaction) = next_component(ctx, &prefix).await else {
+        reply.delete(ctx).await?;
+        return Ok(());
+    };
+    let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind else {
+        bail!("Expected a language");
+    };
+    let code = values.first().context("Expected a language")?;
+    let target =
+        Language::from_code(code).with_context(|| format!("Unknown language `{}`", code))?;
+    data.database.use_language(ctx.author().id, target.code)?;
+
+    interaction
+        .create_response(ctx, CreateInteractionResponse::Acknowledge)
+        .await?;
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .content(format!("{}\u{200b}", data.loading_emoji))
+                .components(Vec::new()),
+        )
+        .await?;
+
+    let mut settings = data.channel_settings(message.channel_id);
+    settings.translate_embeds = true;
+    settings.translate_attachments = true;
+    if !settings.languages.contains(&target) {
+        settings.languages.push(target);
+    }
+    let translation = Translation::new(ctx.serenity_context(), data, &message, &settings).await?;
+    if translation.detection.language == target {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content(format!("The message is already in {}", target.name)),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let translated = translation.translate(target).await?;
+    let post = CreateButton::new(format!("{}-post", prefix))
+        .label("Post in channel")
+        .style(ButtonStyle::Secondary);
+    reply
+        .edit(
+            ctx,
+            CreateReply {
+                content: Some(format!("{} {}", target.flag, translated.content)),
+                embeds: translated.embeds,
+                attachments: translated.attachments,
+                components: Some(vec![CreateActionRow::Buttons(vec![post])]),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let Some(interaction) = next_component(ctx, &prefix).await else {
+        reply
+            .edit(ctx, CreateReply::default().components(Vec::new()))
+            .await?;
+        return Ok(());
+    };
+    let opted_out = data.opted_out(message.author.id, message.guild_id)?;
+    let response = match opted_out {
+        true => CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(OPTED_OUT)
+                .ephemeral(true),
+        ),
+        false => CreateInteractionResponse::Acknowledge,
+    };
+    interaction.create_response(ctx, response).await?;
+    reply
+        .edit(ctx, CreateReply::default().components(Vec::new()))
+        .await?;
+    if opted_out {
+        return Ok(());
+    }
+
+    let webhook = data
+        .webhook(ctx.serenity_context(), message.channel_id)
+        .await?;
+    post_translation(ctx, &webhook, &message, &translation, target).await?;
+
+    Ok(())
+}
+
+/// Posts the translation of `message` in its channel, recreating the webhook if it was deleted
+async fn post_translation(
+    ctx: Context<'_>,
+    webhook: &TranslateHook,
+    message: &Message,
+    translation: &Translation<'_>,
+    target: &Language,
+) -> Result<()> {
+    let framework = ctx.framework();
+    ctx.data()
+        .with_webhook(ctx.serenity_context(), webhook, |webhook| async move {
+            webhook
+                .translate_reply(
+                    ctx.serenity_context(),
+                    &framework,
+                    message,
+                    translation,
+                    target,
+                )
+                .await
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Waits for the author to use a component of the command with the `prefix` id
+async fn next_component(ctx: Context<'_>, prefix: &str) -> Option<ComponentInteraction> {
+    let prefix = prefix.to_string();
+
+    ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |interaction| interaction.data.custom_id.starts_with(&prefix))
+        .timeout(COMPONENT_TIMEOUT)
+        .await
+}
+
+/// Set the server's translation budget
+///
+/// Automatic translation is paused once the server translated this many characters in a month. Use `0` to remove the budget.
+#[command(
+    prefix_command,
+    slash_command,
+    category = "Translation",
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    track_edits
+)]
+pub async fn budget(
+    ctx: Context<'_>,
+    #[description = "Characters per month, 0 for no budget"] characters: u64,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().context("Not in a server")?;
+    let budget = (characters > 0).then_some(characters);
+
+    ctx.data().database.set_budget(guild_id, budget)?;
+    match budget {
+        Some(budget) => {
+            ctx.reply(format!(
+                "Budget set to `{}` characters per month",
+                budget.to_formatted_string(&Locale::en)
+            ))
+            .await?
+        }
+        None => ctx.reply("Budget removed").await?,
+    };
+
     Ok(())
 }
 
 /// Show usage
 ///
-/// Shows the current usage of the DeepL API limit.
+/// Shows the current usage of the translation API limit, the server's budget and the translation cache.
 #[command(
     prefix_command,
     slash_command,
@@ -67,16 +863,47 @@ pub async fn translate(ctx: Context<'_>, message: Message) -> Result<()> {
     ephemeral
 )]
 pub async fn usage(ctx: Context<'_>) -> Result<()> {
-    let usage = ctx.data().deepl.get_usage().await?;
+    let usage = ctx.data().translator.usage().await?;
     let percentage_used = (usage.character_count as f32 / usage.character_limit as f32) * 100_f32;
 
-    ctx.reply(format!(
+    let cache = &ctx.data().translation_cache;
+    let lookups = cache.hits() + cache.misses();
+    let hit_rate = match lookups {
+        0 => 0_f32,
+        lookups => (cache.hits() as f32 / lookups as f32) * 100_f32,
+    };
+
+    let mut lines = vec![format!(
         "Used `{}/{}` ({:.2}%) characters",
         usage.character_count.to_formatted_string(&Locale::en),
         usage.character_limit.to_formatted_string(&Locale::en),
-        percentage_used
-    ))
-    .await?;
+        percentage_used,
+    )];
+
+    if let Some(guild_id) = ctx.guild_id() {
+        let database = &ctx.data().database;
+        let used = database.monthly_usage(guild_id)?;
+        lines.push(match database.budget(guild_id)? {
+            Some(budget) => format!(
+                "This server used `{}/{}` ({:.2}%) characters this month",
+                used.to_formatted_string(&Locale::en),
+                budget.to_formatted_string(&Locale::en),
+                (used as f32 / budget as f32) * 100_f32
+            ),
+            None => format!(
+                "This server used `{}` characters this month",
+                used.to_formatted_string(&Locale::en)
+            ),
+        });
+    }
+
+    lines.push(format!(
+        "Cache hits `{}/{}` ({:.2}%)",
+        cache.hits().to_formatted_string(&Locale::en),
+        lookups.to_formatted_string(&Locale::en),
+        hit_rate
+    ));
+    ctx.reply(lines.join("\n")).await?;
 
     Ok(())
 }
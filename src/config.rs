@@ -1,22 +1,47 @@
+use crate::translator::Backend;
+
 use anyhow::Result;
+use std::net::SocketAddr;
 
 use figment::{
     providers::{Env, Format, Toml},
-    Error, Figment,
+    Figment,
 };
 use secrecy::SecretString;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub translator: Backend,
     pub deepl_auth_key: SecretString,
     pub discord_token: SecretString,
     pub loading_guild_id: u64,
     pub loading_emoji_id: u64,
+    #[serde(default = "default_database_path")]
+    pub database_path: String,
+    #[serde(default = "default_link_retention_days")]
+    pub link_retention_days: u64,
+    /// Languages used outside of autotranslate channels, e.g. `en,ja`
+    #[serde(default = "default_languages")]
+    pub default_languages: String,
+    /// Translations kept in memory
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize,
+    /// Whether to also keep translations in the database
+    #[serde(default)]
+    pub persist_cache: bool,
+    #[serde(default = "default_cache_retention_days")]
+    pub cache_retention_days: u64,
+    /// How long billed characters are kept in the usage ledger
+    #[serde(default = "default_usage_retention_days")]
+    pub usage_retention_days: u64,
+    /// Where to serve Prometheus metrics, e.g. `0.0.0.0:9100`, disabled if missing
+    pub metrics_address: Option<SocketAddr>,
 }
 
 impl Config {
-    pub fn new() -> Result<Self, Error> {
+    pub fn new() -> Result<Self> {
         let config = Figment::new()
             .merge(Env::raw())
             .merge(Toml::file("Config.toml"))
@@ -25,3 +50,27 @@ impl Config {
         Ok(config)
     }
 }
+
+fn default_database_path() -> String {
+    "translate-bot.db".to_string()
+}
+
+fn default_link_retention_days() -> u64 {
+    7
+}
+
+fn default_languages() -> String {
+    "en,ja".to_string()
+}
+
+fn default_cache_size() -> usize {
+    1000
+}
+
+fn default_cache_retention_days() -> u64 {
+    30
+}
+
+fn default_usage_retention_days() -> u64 {
+    365
+}
@@ -1,40 +1,55 @@
-use crate::{Config, TranslateHook};
+use crate::database::{Database, MessageLink, StoredBridge, StoredChannel, UsageRecord};
+use crate::error::is_channel_gone;
+use crate::language::{self, Language};
+use crate::translation::Origin;
+use crate::translator::cache::{Cache, Cached};
+use crate::translator::{self, Options, Translator};
+use crate::webhook::{self, TranslateHook};
+use crate::{metrics, Config};
 
-use anyhow::Result;
-use std::sync::Arc;
+use anyhow::{bail, Context as _, Result};
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
 use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, error, info, warn};
 
 use poise::serenity_prelude as serenity;
-use serenity::{ChannelId, Context, Emoji, EmojiId, GuildId, Message, MessageId};
+use serenity::{
+    ChannelId, Context, Emoji, EmojiId, GuildId, Message, MessageId, Timestamp, UserId, Webhook,
+    WebhookId,
+};
 
 use dashmap::DashMap;
-use deepl::DeepLApi;
 use secrecy::ExposeSecret;
 
-const EDIT_DURATION: Duration = Duration::from_secs(5 * 60); // 10 minutes
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60); // 1 hour
+/// How long automatic translation is paused once the translator's quota is used up
+const QUOTA_PAUSE: Duration = Duration::from_secs(60 * 60); // 1 hour
+/// How long a message isn't translated again when reacting with the same flag
+const REACTION_COOLDOWN: Duration = Duration::from_secs(24 * 60 * 60); // 1 day
 
 pub struct Data {
-    pub deepl: DeepLApi,
+    pub translator: Box<dyn Translator>,
+    pub translation_cache: Arc<Cache>,
+    pub database: Arc<Database>,
     pub loading_emoji: Emoji,
     pub autotranslate_channels: DashMap<ChannelId, Channel>,
-    pub autotranslate_edit_tracker: Arc<DashMap<MessageId, TrackedMessage>>,
+    /// Both ends of every bridge
+    pub bridges: DashMap<ChannelId, Bridge>,
+    /// Webhooks by the channel they belong to, shared by every channel and command posting there
+    webhooks: DashMap<ChannelId, TranslateHook>,
+    /// Translations triggered by flag reactions, with the user they were sent to if private
+    reaction_translations: DashMap<(MessageId, &'static str, Option<UserId>), Instant>,
+    /// When the translator last reported its quota as used up
+    quota_exceeded_at: Mutex<Option<Instant>>,
+    pub default_settings: Settings,
     pub config: Config,
     pub start_time: Instant,
 }
 
 impl Data {
     pub async fn new(ctx: &Context, config: Config) -> Result<Self> {
-        let autotranslate_edit_tracker = Arc::new(DashMap::new());
-        let tracker = autotranslate_edit_tracker.clone();
-
-        tokio::spawn(async move {
-            loop {
-                sleep(Duration::from_secs(10)).await;
-                tracker.retain(|_, v: &mut TrackedMessage| !v.is_expired());
-                tracker.shrink_to_fit();
-            }
-        });
-
         let loading_emoji = ctx
             .http
             .get_emoji(
@@ -43,55 +58,673 @@ impl Data {
             )
             .await?;
 
+        let default_settings = Settings {
+            languages: language::parse_list(&config.default_languages)?,
+            min_confidence: 0.0,
+            options: Options::default(),
+            translate_embeds: false,
+            translate_attachments: false,
+            context_messages: 0,
+            relay: false,
+        };
+        let database = Arc::new(Database::open(&config.database_path)?);
+        let autotranslate_channels = load_channels(ctx, &database, &default_settings).await?;
+        let bridges = load_bridges(ctx, &database).await?;
+        let webhooks = DashMap::new();
+        let stored_webhooks = autotranslate_channels
+            .iter()
+            .map(|channel| channel.webhook.clone())
+            .chain(bridges.iter().map(|bridge| bridge.webhook.clone()));
+        for webhook in stored_webhooks {
+            if let Some(channel_id) = webhook.webhook.channel_id {
+                webhooks.insert(channel_id, webhook);
+            }
+        }
+
+        let translation_cache = Arc::new(Cache::new(
+            config.cache_size,
+            config.persist_cache.then(|| database.clone()),
+        ));
+        let translator = Box::new(Cached::new(
+            translator::from_config(&config),
+            translation_cache.clone(),
+        ));
+
+        if let Some(address) = config.metrics_address {
+            let database = database.clone();
+            tokio::spawn(async move {
+                if let Err(err) = metrics::serve(address, database).await {
+                    error!("Failed to serve metrics: {:#}", err);
+                }
+            });
+        }
+
+        let pruned_database = database.clone();
+        let link_retention = days(config.link_retention_days);
+        let cache_retention = days(config.cache_retention_days);
+        let usage_retention = days(config.usage_retention_days);
+        tokio::spawn(async move {
+            loop {
+                match pruned_database.prune_links(retention_cutoff(link_retention)) {
+                    Ok(pruned) => debug!("Pruned {} message links", pruned),
+                    Err(err) => error!("Failed to prune message links: {:#}", err),
+                }
+                match pruned_database.prune_cache(retention_cutoff(cache_retention)) {
+                    Ok(pruned) => debug!("Pruned {} cached translations", pruned),
+                    Err(err) => error!("Failed to prune cached translations: {:#}", err),
+                }
+                match pruned_database.prune_usage(retention_cutoff(usage_retention)) {
+                    Ok(pruned) => debug!("Pruned {} usage records", pruned),
+                    Err(err) => error!("Failed to prune usage records: {:#}", err),
+                }
+                sleep(PRUNE_INTERVAL).await;
+            }
+        });
+
         Ok(Self {
-            deepl: DeepLApi::with(config.deepl_auth_key.expose_secret()).new(),
+            translator,
+            translation_cache,
+            database,
             loading_emoji,
+            autotranslate_channels,
+            bridges,
+            webhooks,
+            reaction_translations: DashMap::new(),
+            quota_exceeded_at: Mutex::new(None),
+            default_settings,
+            config,
+            start_time: Instant::now(),
+        })
+    }
+
+    /// Data with `translator`, an in-memory database and nothing loaded from Discord
+    #[cfg(test)]
+    pub fn for_tests(translator: Box<dyn Translator>) -> Self {
+        let config = Config {
+            translator: translator::Backend::default(),
+            deepl_auth_key: String::new().into(),
+            discord_token: String::new().into(),
+            loading_guild_id: 1,
+            loading_emoji_id: 1,
+            database_path: ":memory:".to_string(),
+            link_retention_days: 7,
+            default_languages: "en,ja".to_string(),
+            cache_size: 1,
+            persist_cache: false,
+            cache_retention_days: 30,
+            usage_retention_days: 365,
+            metrics_address: None,
+        };
+
+        Self {
+            translator,
+            translation_cache: Arc::new(Cache::new(config.cache_size, None)),
+            database: Arc::new(Database::open(&config.database_path).unwrap()),
+            loading_emoji: serenity::json::from_str(r#"{"id": "1", "name": "loading"}"#).unwrap(),
             autotranslate_channels: DashMap::new(),
-            autotranslate_edit_tracker,
+            bridges: DashMap::new(),
+            webhooks: DashMap::new(),
+            reaction_translations: DashMap::new(),
+            quota_exceeded_at: Mutex::new(None),
+            default_settings: Settings {
+                languages: language::parse_list(&config.default_languages).unwrap(),
+                min_confidence: 0.0,
+                options: Options::default(),
+                translate_embeds: false,
+                translate_attachments: false,
+                context_messages: 0,
+                relay: false,
+            },
             config,
             start_time: Instant::now(),
+        }
+    }
+
+    /// Enables automatic translation in `channel_id`
+    ///
+    /// Enabling it again keeps who enabled it and when, only replacing the webhook and settings.
+    pub fn enable_autotranslate(
+        &self,
+        channel_id: ChannelId,
+        guild_id: Option<GuildId>,
+        enabled_by: UserId,
+        webhook: TranslateHook,
+        settings: Settings,
+    ) -> Result<()> {
+        let (enabled_by, enabled_at) = self
+            .autotranslate_channels
+            .get(&channel_id)
+            .map_or((enabled_by, Timestamp::now()), |channel| {
+                (channel.enabled_by, channel.enabled_at)
+            });
+        let channel = Channel::new(guild_id, webhook, enabled_by, enabled_at, settings);
+        self.database
+            .insert_channel(&channel.to_stored(channel_id))?;
+        self.autotranslate_channels.insert(channel_id, channel);
+
+        Ok(())
+    }
+
+    /// Changes the settings of an autotranslate channel
+    pub fn update_settings(
+        &self,
+        channel_id: ChannelId,
+        update: impl FnOnce(&mut Settings),
+    ) -> Result<()> {
+        let Some(mut channel) = self.autotranslate_channels.get_mut(&channel_id) else {
+            bail!("Translation isn't enabled in this channel");
+        };
+        update(&mut channel.settings);
+        self.database
+            .insert_channel(&channel.to_stored(channel_id))?;
+
+        Ok(())
+    }
+
+    /// The settings used for translations in `channel_id`
+    ///
+    /// Bridged channels translate between the languages of both ends.
+    pub fn channel_settings(&self, channel_id: ChannelId) -> Settings {
+        if let Some(channel) = self.autotranslate_channels.get(&channel_id) {
+            return channel.settings.clone();
+        }
+
+        let mut settings = self.default_settings.clone();
+        if let Some((_, language)) = self.bridge_peer(channel_id) {
+            let own = self.bridges.get(&channel_id).map(|bridge| bridge.language);
+            settings.languages = own.into_iter().chain([language]).collect();
+        }
+
+        settings
+    }
+
+    /// Pairs two channels, messages in each are translated into the other
+    pub fn create_bridge(&self, ends: [(ChannelId, Bridge); 2]) -> Result<()> {
+        for (channel_id, bridge) in ends {
+            self.database.insert_bridge(&bridge.to_stored(channel_id))?;
+            self.bridges.insert(channel_id, bridge);
+        }
+
+        Ok(())
+    }
+
+    /// Changes the language of the end of a bridge in `channel_id`
+    pub fn set_bridge_language(
+        &self,
+        channel_id: ChannelId,
+        language: &'static Language,
+    ) -> Result<()> {
+        let Some(mut bridge) = self.bridges.get_mut(&channel_id) else {
+            bail!("This channel isn't bridged");
+        };
+        bridge.language = language;
+        self.database.insert_bridge(&bridge.to_stored(channel_id))?;
+
+        Ok(())
+    }
+
+    /// Removes the bridge of `channel_id` from both ends, returning the other one
+    pub fn remove_bridge(&self, channel_id: ChannelId) -> Result<Option<ChannelId>> {
+        let Some((_, bridge)) = self.bridges.remove(&channel_id) else {
+            return Ok(None);
+        };
+        self.bridges.remove(&bridge.peer_id);
+        self.database.delete_bridge(channel_id)?;
+        self.database.delete_bridge(bridge.peer_id)?;
+
+        Ok(Some(bridge.peer_id))
+    }
+
+    /// The channel messages in `channel_id` are relayed to and its language, if it's bridged
+    pub fn bridge_peer(&self, channel_id: ChannelId) -> Option<(ChannelId, &'static Language)> {
+        let peer_id = self.bridges.get(&channel_id)?.peer_id;
+        let language = self.bridges.get(&peer_id)?.language;
+
+        Some((peer_id, language))
+    }
+
+    /// The webhook posting the translations of messages in `channel_id`
+    pub fn response_webhook(&self, channel_id: ChannelId) -> Option<TranslateHook> {
+        if let Some(channel) = self.autotranslate_channels.get(&channel_id) {
+            return Some(channel.webhook.clone());
+        }
+
+        let (peer_id, _) = self.bridge_peer(channel_id)?;
+        self.bridges.get(&peer_id).map(|peer| peer.webhook.clone())
+    }
+
+    /// Whether `webhook_id` posts translations in `channel_id`, which aren't translated again
+    pub fn is_own_webhook(&self, channel_id: ChannelId, webhook_id: WebhookId) -> bool {
+        let autotranslate = self.autotranslate_channels.get(&channel_id);
+        let bridge = self.bridges.get(&channel_id);
+
+        autotranslate.is_some_and(|channel| channel.webhook.webhook.id == webhook_id)
+            || bridge.is_some_and(|bridge| bridge.webhook.webhook.id == webhook_id)
+            // Commands and reactions post through webhooks of the channel or its parent
+            || self
+                .webhooks
+                .iter()
+                .any(|webhook| webhook.webhook.id == webhook_id)
+    }
+
+    pub fn disable_autotranslate(&self, channel_id: ChannelId) -> Result<()> {
+        self.database.delete_channel(channel_id)?;
+        self.autotranslate_channels.remove(&channel_id);
+
+        Ok(())
+    }
+
+    /// The webhook posting translations in `channel_id`, only looked up or created the first time
+    pub async fn webhook(&self, ctx: &Context, channel_id: ChannelId) -> Result<TranslateHook> {
+        let webhook_channel = webhook::webhook_channel(ctx, channel_id).await?;
+        if let Some(webhook) = self.webhooks.get(&webhook_channel) {
+            return Ok(webhook.clone());
+        }
+
+        let webhook = TranslateHook::new(ctx, &webhook_channel).await?;
+        self.webhooks.insert(webhook_channel, webhook.clone());
+
+        Ok(webhook)
+    }
+
+    /// Runs `operation` with `webhook`, once more with a new webhook if it was deleted
+    pub async fn with_webhook<T, Fut>(
+        &self,
+        ctx: &Context,
+        webhook: &TranslateHook,
+        operation: impl Fn(TranslateHook) -> Fut,
+    ) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        match operation(webhook.clone()).await {
+            Err(err) if webhook::is_unknown_webhook(&err) => {
+                let webhook = self.recreate_webhook(ctx, webhook).await?;
+                operation(webhook).await
+            }
+            result => result,
+        }
+    }
+
+    /// Replaces the deleted webhook `broken` everywhere it's used
+    async fn recreate_webhook(
+        &self,
+        ctx: &Context,
+        broken: &TranslateHook,
+    ) -> Result<TranslateHook> {
+        let broken_id = broken.webhook.id;
+        let channel_id = broken
+            .webhook
+            .channel_id
+            .context("Webhook isn't in a channel")?;
+        warn!("Webhook for {} was deleted, recreating", channel_id);
+
+        self.webhooks
+            .remove_if(&channel_id, |_, webhook| webhook.webhook.id == broken_id);
+        let webhook = self.webhook(ctx, channel_id).await?;
+
+        for mut channel in self.autotranslate_channels.iter_mut() {
+            if channel.webhook.webhook.id == broken_id {
+                channel.webhook = webhook.clone();
+                self.database
+                    .insert_channel(&channel.to_stored(*channel.key()))?;
+            }
+        }
+        for mut bridge in self.bridges.iter_mut() {
+            if bridge.webhook.webhook.id == broken_id {
+                bridge.webhook = webhook.clone();
+                self.database
+                    .insert_bridge(&bridge.to_stored(*bridge.key()))?;
+            }
+        }
+
+        Ok(webhook)
+    }
+
+    /// Remembers which webhook message holds the translation of `message`
+    pub fn link_message(
+        &self,
+        message: &Message,
+        response: &Message,
+        thread_id: Option<ChannelId>,
+        target_lang: &str,
+    ) -> Result<()> {
+        self.database.insert_link(&MessageLink {
+            message_id: message.id,
+            channel_id: message.channel_id,
+            response_id: response.id,
+            thread_id,
+            target_lang: target_lang.to_string(),
+            linked_at: Timestamp::now(),
         })
     }
 
-    pub fn enable_autotranslate(&self, channel_id: ChannelId, webhook: TranslateHook) {
-        self.autotranslate_channels
-            .insert(channel_id, Channel::new(webhook));
+    pub fn message_links(&self, message_id: MessageId) -> Result<Vec<MessageLink>> {
+        let cutoff = retention_cutoff(days(self.config.link_retention_days));
+        self.database.links(message_id, cutoff)
+    }
+
+    /// Adds the characters billed for a translation to the usage ledger
+    pub fn record_usage(&self, origin: &Origin, characters: u64) -> Result<()> {
+        if characters == 0 {
+            return Ok(());
+        }
+
+        self.database.record_usage(&UsageRecord {
+            guild_id: origin.guild_id,
+            channel_id: origin.channel_id,
+            user_id: origin.user_id,
+            characters,
+            recorded_at: Timestamp::now(),
+        })
+    }
+
+    /// Whether messages of `user_id` in `guild_id` are left untranslated
+    ///
+    /// Opting out is ignored in servers that don't allow it.
+    pub fn opted_out(&self, user_id: UserId, guild_id: Option<GuildId>) -> Result<bool> {
+        if let Some(guild_id) = guild_id {
+            if !self.database.allows_opt_out(guild_id)? {
+                return Ok(false);
+            }
+        }
+
+        self.database.opted_out(user_id, guild_id)
+    }
+
+    /// Claims translating `message_id` into `target` for a flag reaction, privately for `user_id`
+    ///
+    /// Returns `false` if it was already translated recently, so reacting again doesn't repeat it.
+    pub fn claim_reaction(
+        &self,
+        message_id: MessageId,
+        target: &'static Language,
+        user_id: Option<UserId>,
+    ) -> bool {
+        self.reaction_translations
+            .retain(|_, claimed_at| claimed_at.elapsed() < REACTION_COOLDOWN);
+        self.reaction_translations
+            .insert((message_id, target.code, user_id), Instant::now())
+            .is_none()
+    }
+
+    /// Lets a failed reaction translation be retried
+    pub fn release_reaction(
+        &self,
+        message_id: MessageId,
+        target: &'static Language,
+        user_id: Option<UserId>,
+    ) {
+        self.reaction_translations
+            .remove(&(message_id, target.code, user_id));
+    }
+
+    /// Pauses automatic translation for a while, so messages aren't sent to an exhausted translator
+    pub fn pause_for_quota(&self) {
+        let mut exceeded_at = self.quota_exceeded_at.lock().unwrap();
+        if exceeded_at.is_none() {
+            error!(
+                "The translation quota is used up, pausing automatic translation for {:?}",
+                QUOTA_PAUSE
+            );
+        }
+        *exceeded_at = Some(Instant::now());
+    }
+
+    /// Whether automatic translation is paused by [`Data::pause_for_quota`]
+    pub fn quota_exceeded(&self) -> bool {
+        let mut exceeded_at = self.quota_exceeded_at.lock().unwrap();
+        if exceeded_at.is_some_and(|exceeded_at| exceeded_at.elapsed() >= QUOTA_PAUSE) {
+            info!("Resuming automatic translation");
+            *exceeded_at = None;
+        }
+
+        exceeded_at.is_some()
+    }
+
+    /// Whether `guild_id` used up its monthly budget, pausing automatic translation
+    pub fn budget_exceeded(&self, guild_id: GuildId) -> Result<bool> {
+        let Some(budget) = self.database.budget(guild_id)? else {
+            return Ok(false);
+        };
+
+        Ok(self.database.monthly_usage(guild_id)? >= budget)
+    }
+}
+
+fn days(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+fn retention_cutoff(retention: Duration) -> Timestamp {
+    let cutoff = Timestamp::now().unix_timestamp() - retention.as_secs() as i64;
+    Timestamp::from_unix_timestamp(cutoff).unwrap_or_default()
+}
+
+async fn load_channels(
+    ctx: &Context,
+    database: &Database,
+    default_settings: &Settings,
+) -> Result<DashMap<ChannelId, Channel>> {
+    let channels = DashMap::new();
+
+    for stored in database.channels()? {
+        let channel_id = stored.channel_id;
+        let languages = language::parse_list(&stored.languages).unwrap_or_else(|err| {
+            warn!("Invalid languages in {}: {}", channel_id, err);
+            default_settings.languages.clone()
+        });
+        let settings = Settings {
+            languages,
+            min_confidence: stored.min_confidence,
+            options: stored.options,
+            translate_embeds: stored.translate_embeds,
+            translate_attachments: stored.translate_attachments,
+            context_messages: stored.context_messages,
+            relay: false,
+        };
+        let webhook =
+            match stored_webhook(ctx, channel_id, stored.webhook_id, &stored.webhook_token).await {
+                Ok(webhook) => webhook,
+                Err(err) if is_channel_gone(&err) => {
+                    warn!("Disabling autotranslate in {}: {}", channel_id, err);
+                    database.delete_channel(channel_id)?;
+                    continue;
+                }
+                // Kept for the next start, e.g. if Discord couldn't be reached
+                Err(err) => {
+                    error!("Skipping autotranslate in {}: {}", channel_id, err);
+                    continue;
+                }
+            };
+
+        let channel = Channel::new(
+            stored.guild_id,
+            webhook,
+            stored.enabled_by,
+            stored.enabled_at,
+            settings,
+        );
+        database.insert_channel(&channel.to_stored(channel_id))?;
+        channels.insert(channel_id, channel);
+    }
+
+    info!("Loaded {} autotranslate channels", channels.len());
+    Ok(channels)
+}
+
+/// Loads both ends of every bridge, dropping bridges with an end whose channel is gone
+///
+/// Bridges with an end that can't be restored for now, e.g. if Discord can't be reached, are
+/// kept for the next start.
+async fn load_bridges(ctx: &Context, database: &Database) -> Result<DashMap<ChannelId, Bridge>> {
+    let bridges = DashMap::new();
+    let mut skipped = HashSet::new();
+
+    for stored in database.bridges()? {
+        let channel_id = stored.channel_id;
+        let Some(language) = Language::from_code(&stored.language) else {
+            warn!(
+                "Invalid language in bridge {}: {}",
+                channel_id, stored.language
+            );
+            database.delete_bridge(channel_id)?;
+            continue;
+        };
+        let webhook =
+            match stored_webhook(ctx, channel_id, stored.webhook_id, &stored.webhook_token).await {
+                Ok(webhook) => webhook,
+                Err(err) if is_channel_gone(&err) => {
+                    warn!("Removing the bridge of {}: {}", channel_id, err);
+                    database.delete_bridge(channel_id)?;
+                    continue;
+                }
+                Err(err) => {
+                    error!("Skipping the bridge of {}: {}", channel_id, err);
+                    skipped.insert(channel_id);
+                    continue;
+                }
+            };
+
+        let bridge = Bridge {
+            guild_id: stored.guild_id,
+            peer_id: stored.peer_id,
+            language,
+            webhook,
+        };
+        database.insert_bridge(&bridge.to_stored(channel_id))?;
+        bridges.insert(channel_id, bridge);
     }
 
-    pub fn track_edits(&self, message: Message, response: Message) {
-        self.autotranslate_edit_tracker
-            .insert(message.id, TrackedMessage::new(message, response));
+    let orphans = bridges
+        .iter()
+        .filter(|bridge| !bridges.contains_key(&bridge.peer_id))
+        .map(|bridge| *bridge.key())
+        .collect::<Vec<_>>();
+    for channel_id in orphans {
+        if let Some((_, bridge)) = bridges.remove(&channel_id) {
+            if !skipped.contains(&bridge.peer_id) {
+                database.delete_bridge(channel_id)?;
+            }
+        }
+    }
+
+    info!("Loaded {} bridges", bridges.len() / 2);
+    Ok(bridges)
+}
+
+/// The stored webhook of `channel_id`, recreated if it's unavailable
+async fn stored_webhook(
+    ctx: &Context,
+    channel_id: ChannelId,
+    webhook_id: WebhookId,
+    token: &str,
+) -> Result<TranslateHook> {
+    match Webhook::from_id_with_token(ctx, webhook_id, token).await {
+        Ok(webhook) => Ok(TranslateHook { webhook }),
+        Err(err) => {
+            warn!(
+                "Webhook for {} is unavailable ({}), recreating",
+                channel_id, err
+            );
+            TranslateHook::new(ctx, &channel_id).await
+        }
     }
 }
 
 pub struct Channel {
+    pub guild_id: Option<GuildId>,
     pub webhook: TranslateHook,
+    pub enabled_by: UserId,
+    pub enabled_at: Timestamp,
+    pub settings: Settings,
 }
 
-#[allow(clippy::new_without_default)]
 impl Channel {
-    pub fn new(webhook: TranslateHook) -> Self {
-        Self { webhook }
+    pub fn new(
+        guild_id: Option<GuildId>,
+        webhook: TranslateHook,
+        enabled_by: UserId,
+        enabled_at: Timestamp,
+        settings: Settings,
+    ) -> Self {
+        Self {
+            guild_id,
+            webhook,
+            enabled_by,
+            enabled_at,
+            settings,
+        }
+    }
+
+    fn to_stored(&self, channel_id: ChannelId) -> StoredChannel {
+        let webhook = &self.webhook.webhook;
+
+        StoredChannel {
+            channel_id,
+            guild_id: self.guild_id,
+            webhook_id: webhook.id,
+            webhook_token: webhook
+                .token
+                .as_ref()
+                .map(|token| token.expose_secret().clone())
+                .unwrap_or_default(),
+            enabled_by: self.enabled_by,
+            enabled_at: self.enabled_at,
+            languages: language::format_list(&self.settings.languages),
+            min_confidence: self.settings.min_confidence,
+            options: self.settings.options,
+            translate_embeds: self.settings.translate_embeds,
+            translate_attachments: self.settings.translate_attachments,
+            context_messages: self.settings.context_messages,
+        }
     }
 }
 
-#[derive(Clone)]
-pub struct TrackedMessage {
-    pub message: Message,
-    pub response: Message,
-    start_time: Instant,
+/// One end of a bridge between two channels
+pub struct Bridge {
+    pub guild_id: Option<GuildId>,
+    /// The other end
+    pub peer_id: ChannelId,
+    /// The language messages in the channel are translated from and into
+    pub language: &'static Language,
+    /// Posts the translations of the peer's messages in the channel
+    pub webhook: TranslateHook,
 }
 
-impl TrackedMessage {
-    pub fn new(message: Message, response: Message) -> Self {
-        Self {
-            message,
-            response,
-            start_time: Instant::now(),
+impl Bridge {
+    fn to_stored(&self, channel_id: ChannelId) -> StoredBridge {
+        let webhook = &self.webhook.webhook;
+
+        StoredBridge {
+            channel_id,
+            guild_id: self.guild_id,
+            peer_id: self.peer_id,
+            language: self.language.code.to_string(),
+            webhook_id: webhook.id,
+            webhook_token: webhook
+                .token
+                .as_ref()
+                .map(|token| token.expose_secret().clone())
+                .unwrap_or_default(),
         }
     }
+}
 
-    pub fn is_expired(&self) -> bool {
-        self.start_time.elapsed() > EDIT_DURATION
-    }
+#[derive(Clone)]
+pub struct Settings {
+    pub languages: Vec<&'static Language>,
+    /// Messages detected with a lower confidence are not translated
+    pub min_confidence: f64,
+    /// Overridden by the options of the author of each message
+    pub options: Options,
+    /// Whether embeds posted by bots are translated
+    pub translate_embeds: bool,
+    /// Whether small text attachments are translated
+    pub translate_attachments: bool,
+    /// Previous messages given to the translator as context for short messages
+    pub context_messages: u8,
+    /// Whether attachments, stickers and replies are carried over, for bridges where the original
+    /// message isn't visible
+    pub relay: bool,
 }
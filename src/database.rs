@@ -0,0 +1,1018 @@
+use crate::language::Language;
+use crate::translator::{Formality, Options, Response, SplitSentences};
+
+use anyhow::{Context as _, Result};
+use std::sync::{Mutex, MutexGuard};
+
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildId, MessageId, Timestamp, UserId, WebhookId};
+
+use poise::ChoiceParameter;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Schema changes, applied in order. Only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE autotranslate_channels (
+        channel_id    INTEGER PRIMARY KEY,
+        guild_id      INTEGER,
+        webhook_id    INTEGER NOT NULL,
+        webhook_token TEXT    NOT NULL,
+        enabled_by    INTEGER NOT NULL,
+        enabled_at    INTEGER NOT NULL
+    );
+    ",
+    "
+    CREATE TABLE message_links (
+        message_id  INTEGER NOT NULL,
+        channel_id  INTEGER NOT NULL,
+        response_id INTEGER NOT NULL,
+        thread_id   INTEGER,
+        target_lang TEXT    NOT NULL,
+        linked_at   INTEGER NOT NULL,
+        PRIMARY KEY (message_id, response_id)
+    );
+    CREATE INDEX message_links_linked_at ON message_links (linked_at);
+    ",
+    "
+    ALTER TABLE autotranslate_channels ADD COLUMN languages TEXT NOT NULL DEFAULT 'en,ja';
+    ",
+    "
+    ALTER TABLE autotranslate_channels ADD COLUMN min_confidence REAL NOT NULL DEFAULT 0;
+    ",
+    "
+    CREATE TABLE translation_cache (
+        key             TEXT PRIMARY KEY,
+        text            TEXT    NOT NULL,
+        detected_source TEXT,
+        cached_at       INTEGER NOT NULL
+    );
+    CREATE INDEX translation_cache_cached_at ON translation_cache (cached_at);
+    ",
+    "
+    CREATE TABLE glossaries (
+        guild_id    INTEGER NOT NULL,
+        source_lang TEXT    NOT NULL,
+        target_lang TEXT    NOT NULL,
+        name        TEXT    NOT NULL,
+        glossary_id TEXT,
+        PRIMARY KEY (guild_id, source_lang, target_lang)
+    );
+    CREATE TABLE glossary_entries (
+        guild_id    INTEGER NOT NULL,
+        source_lang TEXT    NOT NULL,
+        target_lang TEXT    NOT NULL,
+        source_term TEXT    NOT NULL,
+        target_term TEXT    NOT NULL,
+        PRIMARY KEY (guild_id, source_lang, target_lang, source_term)
+    );
+    ",
+    "
+    ALTER TABLE autotranslate_channels ADD COLUMN formality TEXT;
+    ALTER TABLE autotranslate_channels ADD COLUMN split_sentences TEXT;
+    ALTER TABLE autotranslate_channels ADD COLUMN preserve_formatting INTEGER;
+    CREATE TABLE user_options (
+        user_id             INTEGER PRIMARY KEY,
+        formality           TEXT,
+        split_sentences     TEXT,
+        preserve_formatting INTEGER
+    );
+    ",
+    "
+    CREATE TABLE usage_ledger (
+        guild_id    INTEGER,
+        channel_id  INTEGER NOT NULL,
+        user_id     INTEGER NOT NULL,
+        characters  INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL
+    );
+    CREATE INDEX usage_ledger_guild_id ON usage_ledger (guild_id, recorded_at);
+    CREATE INDEX usage_ledger_recorded_at ON usage_ledger (recorded_at);
+    CREATE TABLE guild_budgets (
+        guild_id   INTEGER PRIMARY KEY,
+        characters INTEGER NOT NULL
+    );
+    ",
+    "
+    ALTER TABLE autotranslate_channels ADD COLUMN translate_embeds INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE autotranslate_channels ADD COLUMN translate_attachments INTEGER NOT NULL DEFAULT 0;
+    ",
+    "
+    ALTER TABLE autotranslate_channels ADD COLUMN context_messages INTEGER NOT NULL DEFAULT 0;
+    ",
+    "
+    CREATE TABLE recent_languages (
+        user_id  INTEGER NOT NULL,
+        language TEXT    NOT NULL,
+        used_at  INTEGER NOT NULL,
+        PRIMARY KEY (user_id, language)
+    );
+    ",
+    "
+    CREATE TABLE user_preferences (
+        user_id  INTEGER PRIMARY KEY,
+        language TEXT NOT NULL,
+        delivery TEXT NOT NULL
+    );
+    ",
+    "
+    CREATE TABLE opt_outs (
+        user_id  INTEGER NOT NULL,
+        guild_id INTEGER NOT NULL, -- 0 for every server
+        PRIMARY KEY (user_id, guild_id)
+    );
+
+    CREATE TABLE guild_settings (
+        guild_id      INTEGER PRIMARY KEY,
+        allow_opt_out INTEGER NOT NULL DEFAULT 1
+    );
+    ",
+    "
+    ALTER TABLE guild_settings ADD COLUMN flag_reactions INTEGER NOT NULL DEFAULT 0;
+    ",
+    "
+    CREATE TABLE bridges (
+        channel_id    INTEGER PRIMARY KEY,
+        guild_id      INTEGER,
+        peer_id       INTEGER NOT NULL,
+        language      TEXT    NOT NULL,
+        webhook_id    INTEGER NOT NULL,
+        webhook_token TEXT    NOT NULL
+    );
+    ",
+    "
+    CREATE TABLE bridge_invites (
+        code       TEXT    PRIMARY KEY,
+        channel_id INTEGER NOT NULL,
+        guild_id   INTEGER NOT NULL,
+        language   TEXT    NOT NULL,
+        created_by INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    ",
+    "
+    CREATE INDEX message_links_response_id ON message_links (response_id);
+    ",
+    "
+    ALTER TABLE translation_cache DROP COLUMN detected_source;
+    ",
+];
+
+pub struct Database {
+    connection: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut connection =
+            Connection::open(path).with_context(|| format!("Failed to open database {}", path))?;
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn channels(&self) -> Result<Vec<StoredChannel>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT channel_id, guild_id, webhook_id, webhook_token, enabled_by, enabled_at,
+                    languages, min_confidence, formality, split_sentences, preserve_formatting,
+                    translate_embeds, translate_attachments, context_messages
+             FROM autotranslate_channels",
+        )?;
+
+        let channels = statement
+            .query_map([], |row| {
+                Ok(StoredChannel {
+                    channel_id: ChannelId::new(row.get(0)?),
+                    guild_id: row.get::<_, Option<u64>>(1)?.map(GuildId::new),
+                    webhook_id: WebhookId::new(row.get(2)?),
+                    webhook_token: row.get(3)?,
+                    enabled_by: UserId::new(row.get(4)?),
+                    enabled_at: Timestamp::from_unix_timestamp(row.get(5)?).unwrap_or_default(),
+                    languages: row.get(6)?,
+                    min_confidence: row.get(7)?,
+                    options: options_from_row(row, 8)?,
+                    translate_embeds: row.get(11)?,
+                    translate_attachments: row.get(12)?,
+                    context_messages: row.get(13)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(channels)
+    }
+
+    pub fn insert_channel(&self, channel: &StoredChannel) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO autotranslate_channels
+             (channel_id, guild_id, webhook_id, webhook_token, enabled_by, enabled_at,
+              languages, min_confidence, formality, split_sentences, preserve_formatting,
+              translate_embeds, translate_attachments, context_messages)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                channel.channel_id.get(),
+                channel.guild_id.map(GuildId::get),
+                channel.webhook_id.get(),
+                channel.webhook_token,
+                channel.enabled_by.get(),
+                channel.enabled_at.unix_timestamp(),
+                channel.languages,
+                channel.min_confidence,
+                channel.options.formality.map(|formality| formality.name()),
+                channel.options.split_sentences.map(|split| split.name()),
+                channel.options.preserve_formatting,
+                channel.translate_embeds,
+                channel.translate_attachments,
+                channel.context_messages,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete_channel(&self, channel_id: ChannelId) -> Result<()> {
+        self.connection().execute(
+            "DELETE FROM autotranslate_channels WHERE channel_id = ?1",
+            params![channel_id.get()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Both ends of every bridge
+    pub fn bridges(&self) -> Result<Vec<StoredBridge>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT channel_id, guild_id, peer_id, language, webhook_id, webhook_token
+             FROM bridges",
+        )?;
+
+        let bridges = statement
+            .query_map([], |row| {
+                Ok(StoredBridge {
+                    channel_id: ChannelId::new(row.get(0)?),
+                    guild_id: row.get::<_, Option<u64>>(1)?.map(GuildId::new),
+                    peer_id: ChannelId::new(row.get(2)?),
+                    language: row.get(3)?,
+                    webhook_id: WebhookId::new(row.get(4)?),
+                    webhook_token: row.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(bridges)
+    }
+
+    pub fn insert_bridge(&self, bridge: &StoredBridge) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO bridges
+             (channel_id, guild_id, peer_id, language, webhook_id, webhook_token)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                bridge.channel_id.get(),
+                bridge.guild_id.map(GuildId::get),
+                bridge.peer_id.get(),
+                bridge.language,
+                bridge.webhook_id.get(),
+                bridge.webhook_token,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete_bridge(&self, channel_id: ChannelId) -> Result<()> {
+        self.connection().execute(
+            "DELETE FROM bridges WHERE channel_id = ?1",
+            params![channel_id.get()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn insert_invite(&self, invite: &BridgeInvite) -> Result<()> {
+        self.connection().execute(
+            "INSERT INTO bridge_invites
+             (code, channel_id, guild_id, language, created_by, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                invite.code,
+                invite.channel_id.get(),
+                invite.guild_id.get(),
+                invite.language,
+                invite.created_by.get(),
+                invite.created_at.unix_timestamp(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// The invite with `code`, unless it was created before `cutoff`
+    pub fn invite(&self, code: &str, cutoff: Timestamp) -> Result<Option<BridgeInvite>> {
+        let connection = self.connection();
+        connection.execute(
+            "DELETE FROM bridge_invites WHERE created_at < ?1",
+            params![cutoff.unix_timestamp()],
+        )?;
+
+        let invite = connection
+            .query_row(
+                "SELECT code, channel_id, guild_id, language, created_by, created_at
+                 FROM bridge_invites
+                 WHERE code = ?1",
+                params![code],
+                |row| {
+                    Ok(BridgeInvite {
+                        code: row.get(0)?,
+                        channel_id: ChannelId::new(row.get(1)?),
+                        guild_id: GuildId::new(row.get(2)?),
+                        language: row.get(3)?,
+                        created_by: UserId::new(row.get(4)?),
+                        created_at: Timestamp::from_unix_timestamp(row.get(5)?).unwrap_or_default(),
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(invite)
+    }
+
+    pub fn delete_invite(&self, code: &str) -> Result<()> {
+        self.connection()
+            .execute("DELETE FROM bridge_invites WHERE code = ?1", params![code])?;
+
+        Ok(())
+    }
+
+    /// Options overridden by `user_id`, all unset if there are none
+    pub fn user_options(&self, user_id: UserId) -> Result<Options> {
+        let options = self
+            .connection()
+            .query_row(
+                "SELECT formality, split_sentences, preserve_formatting
+                 FROM user_options
+                 WHERE user_id = ?1",
+                params![user_id.get()],
+                |row| options_from_row(row, 0),
+            )
+            .optional()?;
+
+        Ok(options.unwrap_or_default())
+    }
+
+    pub fn set_user_options(&self, user_id: UserId, options: &Options) -> Result<()> {
+        if *options == Options::default() {
+            self.connection().execute(
+                "DELETE FROM user_options WHERE user_id = ?1",
+                params![user_id.get()],
+            )?;
+            return Ok(());
+        }
+
+        self.connection().execute(
+            "INSERT OR REPLACE INTO user_options
+             (user_id, formality, split_sentences, preserve_formatting)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                user_id.get(),
+                options.formality.map(|formality| formality.name()),
+                options.split_sentences.map(|split| split.name()),
+                options.preserve_formatting,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn channel_count(&self) -> Result<i64> {
+        let count = self.connection().query_row(
+            "SELECT COUNT(*) FROM autotranslate_channels",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(count)
+    }
+
+    /// Messages with linked translations
+    pub fn tracked_message_count(&self) -> Result<i64> {
+        let count = self.connection().query_row(
+            "SELECT COUNT(DISTINCT message_id) FROM message_links",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(count)
+    }
+
+    /// Languages `user_id` translated to, most recently used first
+    pub fn recent_languages(&self, user_id: UserId) -> Result<Vec<String>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT language FROM recent_languages WHERE user_id = ?1 ORDER BY used_at DESC",
+        )?;
+
+        let languages = statement
+            .query_map(params![user_id.get()], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(languages)
+    }
+
+    pub fn use_language(&self, user_id: UserId, language: &str) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO recent_languages (user_id, language, used_at)
+             VALUES (?1, ?2, ?3)",
+            params![user_id.get(), language, Timestamp::now().unix_timestamp()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Ignored if its language or delivery is no longer known
+    pub fn preference(&self, user_id: UserId) -> Result<Option<Preference>> {
+        let preference = self
+            .connection()
+            .query_row(
+                "SELECT language, delivery FROM user_preferences WHERE user_id = ?1",
+                params![user_id.get()],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?
+            .and_then(|(language, delivery)| {
+                Some(Preference {
+                    language: Language::from_code(&language)?,
+                    delivery: Delivery::from_name(&delivery)?,
+                })
+            });
+
+        Ok(preference)
+    }
+
+    /// Sets the preference of `user_id`, removing it if `None`
+    pub fn set_preference(&self, user_id: UserId, preference: Option<&Preference>) -> Result<()> {
+        match preference {
+            Some(preference) => self.connection().execute(
+                "INSERT OR REPLACE INTO user_preferences (user_id, language, delivery)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    user_id.get(),
+                    preference.language.code,
+                    preference.delivery.name()
+                ],
+            )?,
+            None => self.connection().execute(
+                "DELETE FROM user_preferences WHERE user_id = ?1",
+                params![user_id.get()],
+            )?,
+        };
+
+        Ok(())
+    }
+
+    /// Links a message to a response, keeping when it was first linked if it already was
+    pub fn insert_link(&self, link: &MessageLink) -> Result<()> {
+        self.connection().execute(
+            "INSERT INTO message_links
+             (message_id, channel_id, response_id, thread_id, target_lang, linked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (message_id, response_id) DO UPDATE SET thread_id = excluded.thread_id",
+            params![
+                link.message_id.get(),
+                link.channel_id.get(),
+                link.response_id.get(),
+                link.thread_id.map(ChannelId::get),
+                link.target_lang,
+                link.linked_at.unix_timestamp(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Links of `message_id` made after `since`
+    pub fn links(&self, message_id: MessageId, since: Timestamp) -> Result<Vec<MessageLink>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT message_id, channel_id, response_id, thread_id, target_lang, linked_at
+             FROM message_links
+             WHERE message_id = ?1 AND linked_at >= ?2",
+        )?;
+
+        let links = statement
+            .query_map(
+                params![message_id.get(), since.unix_timestamp()],
+                link_from_row,
+            )?
+            .collect::<Result<_, _>>()?;
+
+        Ok(links)
+    }
+
+    /// The link of the message translated into `response_id`
+    pub fn link_of_response(&self, response_id: MessageId) -> Result<Option<MessageLink>> {
+        let link = self
+            .connection()
+            .query_row(
+                "SELECT message_id, channel_id, response_id, thread_id, target_lang, linked_at
+                 FROM message_links
+                 WHERE response_id = ?1",
+                params![response_id.get()],
+                link_from_row,
+            )
+            .optional()?;
+
+        Ok(link)
+    }
+
+    pub fn delete_links(&self, message_id: MessageId) -> Result<()> {
+        self.connection().execute(
+            "DELETE FROM message_links WHERE message_id = ?1",
+            params![message_id.get()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Deletes links made before `before`, returning how many were removed
+    pub fn prune_links(&self, before: Timestamp) -> Result<usize> {
+        let pruned = self.connection().execute(
+            "DELETE FROM message_links WHERE linked_at < ?1",
+            params![before.unix_timestamp()],
+        )?;
+
+        Ok(pruned)
+    }
+
+    pub fn cached_translation(&self, key: &str) -> Result<Option<Response>> {
+        let response = self
+            .connection()
+            .query_row(
+                "SELECT text FROM translation_cache WHERE key = ?1",
+                params![key],
+                |row| {
+                    Ok(Response {
+                        text: row.get(0)?,
+                        billed_characters: 0,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(response)
+    }
+
+    pub fn cache_translation(&self, key: &str, response: &Response) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO translation_cache (key, text, cached_at)
+             VALUES (?1, ?2, ?3)",
+            params![key, response.text, Timestamp::now().unix_timestamp(),],
+        )?;
+
+        Ok(())
+    }
+
+    /// Deletes translations cached before `before`, returning how many were removed
+    pub fn prune_cache(&self, before: Timestamp) -> Result<usize> {
+        let pruned = self.connection().execute(
+            "DELETE FROM translation_cache WHERE cached_at < ?1",
+            params![before.unix_timestamp()],
+        )?;
+
+        Ok(pruned)
+    }
+
+    pub fn record_usage(&self, usage: &UsageRecord) -> Result<()> {
+        self.connection().execute(
+            "INSERT INTO usage_ledger (guild_id, channel_id, user_id, characters, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                usage.guild_id.map(GuildId::get),
+                usage.channel_id.get(),
+                usage.user_id.get(),
+                usage.characters,
+                usage.recorded_at.unix_timestamp(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Characters billed for `guild_id` since the start of the current month (UTC)
+    pub fn monthly_usage(&self, guild_id: GuildId) -> Result<u64> {
+        let characters = self.connection().query_row(
+            "SELECT COALESCE(SUM(characters), 0)
+             FROM usage_ledger
+             WHERE guild_id = ?1
+               AND recorded_at >= CAST(strftime('%s', 'now', 'start of month') AS INTEGER)",
+            params![guild_id.get()],
+            |row| row.get(0),
+        )?;
+
+        Ok(characters)
+    }
+
+    /// Deletes usage recorded before `before`, returning how many records were removed
+    pub fn prune_usage(&self, before: Timestamp) -> Result<usize> {
+        let pruned = self.connection().execute(
+            "DELETE FROM usage_ledger WHERE recorded_at < ?1",
+            params![before.unix_timestamp()],
+        )?;
+
+        Ok(pruned)
+    }
+
+    /// Monthly character budget of `guild_id`
+    pub fn budget(&self, guild_id: GuildId) -> Result<Option<u64>> {
+        let budget = self
+            .connection()
+            .query_row(
+                "SELECT characters FROM guild_budgets WHERE guild_id = ?1",
+                params![guild_id.get()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(budget)
+    }
+
+    /// Sets the monthly character budget of `guild_id`, removing it if `None`
+    pub fn set_budget(&self, guild_id: GuildId, characters: Option<u64>) -> Result<()> {
+        match characters {
+            Some(characters) => self.connection().execute(
+                "INSERT OR REPLACE INTO guild_budgets (guild_id, characters) VALUES (?1, ?2)",
+                params![guild_id.get(), characters],
+            )?,
+            None => self.connection().execute(
+                "DELETE FROM guild_budgets WHERE guild_id = ?1",
+                params![guild_id.get()],
+            )?,
+        };
+
+        Ok(())
+    }
+
+    /// Whether `user_id` opted out in `guild_id` or everywhere
+    pub fn opted_out(&self, user_id: UserId, guild_id: Option<GuildId>) -> Result<bool> {
+        let opted_out = self.connection().query_row(
+            "SELECT EXISTS (SELECT 1 FROM opt_outs WHERE user_id = ?1 AND guild_id IN (0, ?2))",
+            params![user_id.get(), guild_id.map_or(0, GuildId::get)],
+            |row| row.get(0),
+        )?;
+
+        Ok(opted_out)
+    }
+
+    /// Opts `user_id` out in `guild_id`, or everywhere if `None`
+    pub fn set_opted_out(
+        &self,
+        user_id: UserId,
+        guild_id: Option<GuildId>,
+        opted_out: bool,
+    ) -> Result<()> {
+        let params = params![user_id.get(), guild_id.map_or(0, GuildId::get)];
+        match opted_out {
+            true => self.connection().execute(
+                "INSERT OR IGNORE INTO opt_outs (user_id, guild_id) VALUES (?1, ?2)",
+                params,
+            )?,
+            false => self.connection().execute(
+                "DELETE FROM opt_outs WHERE user_id = ?1 AND guild_id = ?2",
+                params,
+            )?,
+        };
+
+        Ok(())
+    }
+
+    /// Whether members of `guild_id` can opt out, allowed unless set otherwise
+    pub fn allows_opt_out(&self, guild_id: GuildId) -> Result<bool> {
+        let allowed = self
+            .connection()
+            .query_row(
+                "SELECT allow_opt_out FROM guild_settings WHERE guild_id = ?1",
+                params![guild_id.get()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(allowed.unwrap_or(true))
+    }
+
+    pub fn set_allow_opt_out(&self, guild_id: GuildId, allowed: bool) -> Result<()> {
+        self.connection().execute(
+            "INSERT INTO guild_settings (guild_id, allow_opt_out) VALUES (?1, ?2)
+             ON CONFLICT (guild_id) DO UPDATE SET allow_opt_out = excluded.allow_opt_out",
+            params![guild_id.get(), allowed],
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether reacting with a flag translates messages in `guild_id`, off unless set otherwise
+    pub fn flag_reactions(&self, guild_id: GuildId) -> Result<bool> {
+        let enabled = self
+            .connection()
+            .query_row(
+                "SELECT flag_reactions FROM guild_settings WHERE guild_id = ?1",
+                params![guild_id.get()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(enabled.unwrap_or(false))
+    }
+
+    pub fn set_flag_reactions(&self, guild_id: GuildId, enabled: bool) -> Result<()> {
+        self.connection().execute(
+            "INSERT INTO guild_settings (guild_id, flag_reactions) VALUES (?1, ?2)
+             ON CONFLICT (guild_id) DO UPDATE SET flag_reactions = excluded.flag_reactions",
+            params![guild_id.get(), enabled],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn glossaries(&self, guild_id: GuildId) -> Result<Vec<StoredGlossary>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT guild_id, source_lang, target_lang, name, glossary_id
+             FROM glossaries
+             WHERE guild_id = ?1
+             ORDER BY source_lang, target_lang",
+        )?;
+
+        let glossaries = statement
+            .query_map(params![guild_id.get()], glossary_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(glossaries)
+    }
+
+    pub fn glossary(
+        &self,
+        guild_id: GuildId,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Option<StoredGlossary>> {
+        let glossary = self
+            .connection()
+            .query_row(
+                "SELECT guild_id, source_lang, target_lang, name, glossary_id
+                 FROM glossaries
+                 WHERE guild_id = ?1 AND source_lang = ?2 AND target_lang = ?3",
+                params![guild_id.get(), source_lang, target_lang],
+                glossary_from_row,
+            )
+            .optional()?;
+
+        Ok(glossary)
+    }
+
+    pub fn insert_glossary(&self, glossary: &StoredGlossary) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO glossaries
+             (guild_id, source_lang, target_lang, name, glossary_id)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                glossary.guild_id.get(),
+                glossary.source_lang,
+                glossary.target_lang,
+                glossary.name,
+                glossary.glossary_id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Deletes a glossary along with its entries
+    pub fn delete_glossary(
+        &self,
+        guild_id: GuildId,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        for table in ["glossaries", "glossary_entries"] {
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {} WHERE guild_id = ?1 AND source_lang = ?2 AND target_lang = ?3",
+                    table
+                ),
+                params![guild_id.get(), source_lang, target_lang],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn glossary_entries(
+        &self,
+        guild_id: GuildId,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT source_term, target_term
+             FROM glossary_entries
+             WHERE guild_id = ?1 AND source_lang = ?2 AND target_lang = ?3
+             ORDER BY source_term",
+        )?;
+
+        let entries = statement
+            .query_map(params![guild_id.get(), source_lang, target_lang], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn insert_glossary_entry(
+        &self,
+        guild_id: GuildId,
+        source_lang: &str,
+        target_lang: &str,
+        source_term: &str,
+        target_term: &str,
+    ) -> Result<()> {
+        self.connection().execute(
+            "INSERT OR REPLACE INTO glossary_entries
+             (guild_id, source_lang, target_lang, source_term, target_term)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                guild_id.get(),
+                source_lang,
+                target_lang,
+                source_term,
+                target_term
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns whether the entry existed
+    pub fn delete_glossary_entry(
+        &self,
+        guild_id: GuildId,
+        source_lang: &str,
+        target_lang: &str,
+        source_term: &str,
+    ) -> Result<bool> {
+        let deleted = self.connection().execute(
+            "DELETE FROM glossary_entries
+             WHERE guild_id = ?1 AND source_lang = ?2 AND target_lang = ?3 AND source_term = ?4",
+            params![guild_id.get(), source_lang, target_lang, source_term],
+        )?;
+
+        Ok(deleted > 0)
+    }
+}
+
+/// Reads the formality, sentence splitting and formatting columns starting at `index`
+///
+/// Unknown values are treated as unset.
+fn options_from_row(row: &rusqlite::Row<'_>, index: usize) -> rusqlite::Result<Options> {
+    Ok(Options {
+        formality: row
+            .get::<_, Option<String>>(index)?
+            .and_then(|name| Formality::from_name(&name)),
+        split_sentences: row
+            .get::<_, Option<String>>(index + 1)?
+            .and_then(|name| SplitSentences::from_name(&name)),
+        preserve_formatting: row.get(index + 2)?,
+    })
+}
+
+fn link_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageLink> {
+    Ok(MessageLink {
+        message_id: MessageId::new(row.get(0)?),
+        channel_id: ChannelId::new(row.get(1)?),
+        response_id: MessageId::new(row.get(2)?),
+        thread_id: row.get::<_, Option<u64>>(3)?.map(ChannelId::new),
+        target_lang: row.get(4)?,
+        linked_at: Timestamp::from_unix_timestamp(row.get(5)?).unwrap_or_default(),
+    })
+}
+
+fn glossary_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<StoredGlossary> {
+    Ok(StoredGlossary {
+        guild_id: GuildId::new(row.get(0)?),
+        source_lang: row.get(1)?,
+        target_lang: row.get(2)?,
+        name: row.get(3)?,
+        glossary_id: row.get(4)?,
+    })
+}
+
+pub struct StoredChannel {
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    pub webhook_id: WebhookId,
+    pub webhook_token: String,
+    pub enabled_by: UserId,
+    pub enabled_at: Timestamp,
+    /// Comma separated language codes
+    pub languages: String,
+    pub min_confidence: f64,
+    pub options: Options,
+    pub translate_embeds: bool,
+    pub translate_attachments: bool,
+    pub context_messages: u8,
+}
+
+/// One end of a bridge, the other end is stored with the channels swapped
+pub struct StoredBridge {
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    pub peer_id: ChannelId,
+    /// Language code of the channel
+    pub language: String,
+    /// Posts the translations of the peer's messages in the channel
+    pub webhook_id: WebhookId,
+    pub webhook_token: String,
+}
+
+/// An offer to bridge a channel with one in another server, accepted with its code
+pub struct BridgeInvite {
+    pub code: String,
+    pub channel_id: ChannelId,
+    pub guild_id: GuildId,
+    /// Language code of the channel
+    pub language: String,
+    pub created_by: UserId,
+    pub created_at: Timestamp,
+}
+
+pub struct MessageLink {
+    pub message_id: MessageId,
+    pub channel_id: ChannelId,
+    pub response_id: MessageId,
+    pub thread_id: Option<ChannelId>,
+    pub target_lang: String,
+    pub linked_at: Timestamp,
+}
+
+/// Characters billed for one translation
+pub struct UsageRecord {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+    pub characters: u64,
+    pub recorded_at: Timestamp,
+}
+
+pub struct StoredGlossary {
+    pub guild_id: GuildId,
+    pub source_lang: String,
+    pub target_lang: String,
+    pub name: String,
+    /// The translator's id for the glossary, missing while it has no entries
+    pub glossary_id: Option<String>,
+}
+
+/// The language a user wants on-demand translations in, and how to receive them
+pub struct Preference {
+    pub language: &'static Language,
+    pub delivery: Delivery,
+}
+
+#[derive(Clone, Copy, PartialEq, ChoiceParameter)]
+pub enum Delivery {
+    /// Only visible to the user, except for flag reactions which can't be answered privately
+    /// and are sent as direct messages
+    #[name = "only me"]
+    Ephemeral,
+    #[name = "direct message"]
+    DirectMessage,
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .with_context(|| format!("Failed to apply migration {}", index + 1))?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
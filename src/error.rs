@@ -0,0 +1,125 @@
+use crate::language::Language;
+use crate::translator;
+
+use std::fmt::{self, Display};
+
+use poise::serenity_prelude as serenity;
+use serenity::{HttpError, ModelError};
+
+/// Discord's JSON error code for channels that don't exist
+const UNKNOWN_CHANNEL: isize = 10003;
+/// Discord's JSON error code for webhooks that don't exist
+const UNKNOWN_WEBHOOK: isize = 10015;
+/// Discord's JSON error code for channels the bot can't see anymore
+const MISSING_ACCESS: isize = 50001;
+/// Discord's JSON error code for requests over the size limit, e.g. too many characters
+const REQUEST_TOO_LARGE: isize = 40005;
+
+/// Why a message wasn't translated
+///
+/// Some of these are expected and skipped silently, e.g. a message made of emoji, others are
+/// shown to the user or logged.
+#[derive(Debug)]
+pub enum BotError {
+    /// The message has no letters, e.g. only emoji, mentions or links
+    NothingToTranslate,
+    UnknownLanguage,
+    /// The language was detected with less than the channel's minimum confidence
+    NotConfident,
+    AlreadyInLanguage(&'static Language),
+    /// The translation doesn't fit in a message
+    TooLong,
+    /// The webhook was deleted, e.g. by a moderator
+    UnknownWebhook,
+    Translator(translator::Error),
+    /// Boxed as it's much larger than the other variants
+    Discord(Box<serenity::Error>),
+}
+
+impl BotError {
+    /// Whether the message shouldn't be translated, which isn't worth telling anyone about
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Self::NothingToTranslate | Self::UnknownLanguage | Self::NotConfident
+        )
+    }
+
+    /// Whether the bot can't translate anything until someone steps in
+    pub fn is_alert(&self) -> bool {
+        matches!(
+            self,
+            Self::Translator(translator::Error::QuotaExceeded | translator::Error::Auth)
+        )
+    }
+}
+
+impl Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NothingToTranslate => write!(f, "Nothing to translate"),
+            Self::UnknownLanguage => write!(f, "Couldn't detect the language"),
+            Self::NotConfident => write!(f, "Not confident enough about the language"),
+            Self::AlreadyInLanguage(language) => {
+                write!(f, "The text is already in {}", language.name)
+            }
+            Self::TooLong => write!(f, "Translation too long"),
+            Self::UnknownWebhook => write!(f, "The webhook was deleted"),
+            Self::Translator(err) => err.fmt(f),
+            Self::Discord(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Translator(err) => Some(err),
+            Self::Discord(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<translator::Error> for BotError {
+    fn from(err: translator::Error) -> Self {
+        Self::Translator(err)
+    }
+}
+
+impl From<serenity::Error> for BotError {
+    fn from(err: serenity::Error) -> Self {
+        match &err {
+            serenity::Error::Model(
+                ModelError::MessageTooLong(_) | ModelError::EmbedTooLarge(_),
+            ) => Self::TooLong,
+            serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+                match response.error.code {
+                    UNKNOWN_WEBHOOK => Self::UnknownWebhook,
+                    REQUEST_TOO_LARGE => Self::TooLong,
+                    _ => Self::Discord(Box::new(err)),
+                }
+            }
+            _ => Self::Discord(Box::new(err)),
+        }
+    }
+}
+
+/// Whether `err` means a channel is gone for good, deleted or no longer visible to the bot
+///
+/// Anything else, e.g. a network error, may not happen again later.
+pub fn is_channel_gone(err: &anyhow::Error) -> bool {
+    let err = match err.downcast_ref::<BotError>() {
+        Some(BotError::Discord(err)) => err.as_ref(),
+        _ => match err.downcast_ref::<serenity::Error>() {
+            Some(err) => err,
+            None => return false,
+        },
+    };
+
+    matches!(
+        err,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response))
+            if matches!(response.error.code, UNKNOWN_CHANNEL | MISSING_ACCESS)
+    )
+}
@@ -1,6 +1,9 @@
-use crate::{Data, TranslateHook, Translation};
+use crate::commands::translation::OPTED_OUT;
+use crate::error::BotError;
+use crate::{Data, Translation};
 
 use anyhow::{Error, Result};
+use tracing::{debug, error};
 
 use poise::serenity_prelude as serenity;
 use poise::CreateReply;
@@ -38,11 +41,32 @@ pub async fn error_handler(error: FrameworkError<'_>) -> Result<()> {
                 None => message,
             };
 
-            let translation = Translation::new(&framework.user_data.deepl, message)?;
-            TranslateHook::new(ctx, &message.channel_id)
-                .await?
-                .translate_reply(ctx, &framework, message, &translation)
+            let data = framework.user_data;
+            if data.opted_out(message.author.id, message.guild_id)? {
+                message.reply(ctx, format!("{}.", OPTED_OUT)).await?;
+                return Ok(());
+            }
+            let mut settings = data.channel_settings(message.channel_id);
+            settings.translate_embeds = true;
+            settings.translate_attachments = true;
+            let translation = match Translation::new(ctx, data, message, &settings).await {
+                Err(err) if err.is_skip() => {
+                    message.reply(ctx, format!("{}.", err)).await?;
+                    return Ok(());
+                }
+                result => result?,
+            };
+            let webhook = data.webhook(ctx, message.channel_id).await?;
+
+            let translation = &translation;
+            for target in &translation.targets {
+                data.with_webhook(ctx, &webhook, |webhook| async move {
+                    webhook
+                        .translate_reply(ctx, &framework, message, translation, target)
+                        .await
+                })
                 .await?;
+            }
         }
         FrameworkError::MissingBotPermissions {
             missing_permissions,
@@ -59,6 +83,34 @@ pub async fn error_handler(error: FrameworkError<'_>) -> Result<()> {
             ))
             .await?;
         }
+        FrameworkError::Command { error, ctx, .. } if error.is::<BotError>() => {
+            let reply = match error.downcast_ref::<BotError>() {
+                Some(err @ BotError::Discord(_)) => {
+                    error!("Failed to run /{}: {}", ctx.command().qualified_name, err);
+                    "Something went wrong, try again later.".to_string()
+                }
+                Some(err) if err.is_alert() => {
+                    error!("Translations are failing until fixed: {}", err);
+                    format!("{}.", err)
+                }
+                _ => format!("{}.", error),
+            };
+            ctx.send(CreateReply::default().content(reply).ephemeral(true))
+                .await?;
+        }
+        FrameworkError::EventHandler { error, event, .. } => {
+            let event = event.snake_case_name();
+            match error.downcast_ref::<BotError>() {
+                Some(err) if err.is_skip() => debug!("Skipped {}: {}", event, err),
+                Some(err) if err.is_alert() => {
+                    error!(
+                        "Translations are failing until fixed, on {}: {}",
+                        event, err
+                    )
+                }
+                _ => error!("Failed to handle {}: {:#}", event, error),
+            }
+        }
         other => poise::builtins::on_error(other).await?,
     }
 
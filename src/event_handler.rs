@@ -1,10 +1,14 @@
-use crate::{Data, TranslateHook, Translation};
+use crate::language::Language;
+use crate::{metrics, Data, TranslateHook, Translation};
 
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, Context as _, Error, Result};
+use tracing::{debug, warn};
 
 use poise::{serenity_prelude as serenity, FrameworkContext};
-use serenity::FullEvent::{Message, MessageDelete, MessageUpdate};
-use serenity::{Context, FullEvent};
+use serenity::FullEvent::{Message, MessageDelete, MessageUpdate, ReactionAdd};
+use serenity::{
+    ChannelId, Context, Embed, FullEvent, GuildId, MessageUpdateEvent, Reaction, ReactionType,
+};
 
 pub async fn event_handler(
     ctx: &Context,
@@ -12,19 +16,31 @@ pub async fn event_handler(
     framework: FrameworkContext<'_, Data, Error>,
     data: &Data,
 ) -> Result<()> {
-    let deepl = &data.deepl;
-    let tracker = &data.autotranslate_edit_tracker;
+    metrics::GATEWAY_EVENTS
+        .with_label_values(&[event.snake_case_name()])
+        .inc();
+
     let channels = &data.autotranslate_channels;
 
-    let is_enabled = |channel_id| channels.contains_key(channel_id);
-    let get_webhook = |channel_id| -> Result<TranslateHook> {
-        let webhook = channels
-            .get(channel_id)
-            .ok_or_else(|| anyhow!("Webhook not found"))?
-            .webhook
-            .clone();
+    let is_enabled = |channel_id: &ChannelId| {
+        channels.contains_key(channel_id) || data.bridges.contains_key(channel_id)
+    };
+    let get_webhook = |channel_id: &ChannelId| -> Result<TranslateHook> {
+        data.response_webhook(*channel_id)
+            .ok_or_else(|| anyhow!("Webhook not found"))
+    };
+    let is_paused = |guild_id: Option<GuildId>| -> Result<bool> {
+        if data.quota_exceeded() {
+            debug!("Not translating, the translation quota is used up");
+            return Ok(true);
+        }
+        let Some(guild_id) = guild_id else { return Ok(false) };
+        let exceeded = data.budget_exceeded(guild_id)?;
+        if exceeded {
+            debug!("Not translating in {}, its budget is exceeded", guild_id);
+        }
 
-        Ok(webhook)
+        Ok(exceeded)
     };
 
     match event {
@@ -32,54 +48,263 @@ pub async fn event_handler(
             new_message: message,
         } => {
             let author = &message.author;
-            let content = &message.content;
             let channel_id = &message.channel_id;
 
-            if author.bot || content.is_empty() || !is_enabled(channel_id) {
+            if !is_enabled(channel_id) || author.id == ctx.cache.current_user().id {
                 return Ok(());
             }
+            if message
+                .webhook_id
+                .is_some_and(|webhook_id| data.is_own_webhook(*channel_id, webhook_id))
+            {
+                return Ok(());
+            }
+            let webhook = get_webhook(channel_id)?;
 
-            let translation = Translation::new(deepl, message)?;
-            let response = get_webhook(channel_id)?
-                .translate_reply(ctx, &framework, message, &translation)
-                .await?;
+            let mut settings = data.channel_settings(*channel_id);
+            settings.relay = data.bridges.contains_key(channel_id);
+            let has_embeds = settings.translate_embeds && !rich_embeds(&message.embeds).is_empty();
+            let has_attachments = (settings.translate_attachments || settings.relay)
+                && !message.attachments.is_empty();
+            let has_stickers = settings.relay && !message.sticker_items.is_empty();
+            // Bots are only translated for their embeds, e.g. feeds and announcements
+            if author.bot && !has_embeds {
+                return Ok(());
+            }
+            if message.content.is_empty() && !has_embeds && !has_attachments && !has_stickers {
+                return Ok(());
+            }
+            if is_paused(message.guild_id)? || data.opted_out(author.id, message.guild_id)? {
+                return Ok(());
+            }
 
-            data.track_edits(message.clone(), response);
-        }
+            let translation = match Translation::new(ctx, data, message, &settings).await {
+                Err(err) if err.is_skip() => {
+                    debug!("Not translating {}: {}", message.id, err);
+                    return Ok(());
+                }
+                result => result?,
+            };
+            // Bridged channels are translated into the other end instead of inline
+            let (destination, targets) = match data.bridge_peer(*channel_id) {
+                Some((peer_id, language)) => (peer_id, vec![language]),
+                None => (*channel_id, translation.targets.clone()),
+            };
 
-        MessageUpdate { event: message, .. } => {
-            let Some(author) = &message.author else { return Ok(()) };
-            let Some(content) = &message.content else { return Ok(()) };
+            let translation = &translation;
+            for target in targets {
+                let response = data
+                    .with_webhook(ctx, &webhook, |webhook| async move {
+                        webhook
+                            .post(ctx, &framework, message, destination, translation, target)
+                            .await
+                    })
+                    .await?;
 
-            if author.bot || content.is_empty() {
+                data.link_message(
+                    message,
+                    &response,
+                    webhook.thread_id(destination),
+                    target.code,
+                )?;
+            }
+        }
+
+        MessageUpdate {
+            old_if_available: old,
+            event: message,
+            ..
+        } => {
+            if !changes_translation(old.as_ref(), message) {
+                return Ok(());
+            }
+            let links = data.message_links(message.id)?;
+            if links.is_empty() || is_paused(message.guild_id)? {
                 return Ok(());
             }
 
-            let mut tracked = {
-                let Some(tracked) = tracker.get_mut(&message.id) else { return Ok(()) };
-                tracked.clone()
+            let mut updated = message.channel_id.message(ctx, message.id).await?;
+            updated.guild_id = updated.guild_id.or(message.guild_id);
+            if data.opted_out(updated.author.id, updated.guild_id)? {
+                return Ok(());
+            }
+            let mut settings = data.channel_settings(message.channel_id);
+            settings.relay = data.bridges.contains_key(&message.channel_id);
+            // The previous translations are kept if the message became untranslatable
+            let translation = match Translation::new(ctx, data, &updated, &settings).await {
+                Err(err) if err.is_skip() => {
+                    debug!("Not translating the edit of {}: {}", updated.id, err);
+                    return Ok(());
+                }
+                result => result?,
             };
-            message.apply_to_message(&mut tracked.message);
-            let translation = Translation::new(deepl, &tracked.message)?;
+            let webhook = get_webhook(&message.channel_id)?;
 
-            let response = get_webhook(&message.channel_id)?
-                .edit_response(ctx, &tracked.response, &translation)
-                .await?;
+            let translation = &translation;
+            for link in links {
+                let Some(target) = Language::from_code(&link.target_lang) else { continue };
+                let (response_id, thread_id) = (link.response_id, link.thread_id);
+                let response = data
+                    .with_webhook(ctx, &webhook, |webhook| async move {
+                        webhook
+                            .edit_response(ctx, response_id, thread_id, translation, target)
+                            .await
+                    })
+                    .await?;
 
-            data.track_edits(tracked.message.clone(), response);
+                data.link_message(&updated, &response, link.thread_id, target.code)?;
+            }
         }
 
         MessageDelete {
             deleted_message_id: message_id,
             ..
         } => {
-            let Some((_, tracked)) = tracker.remove(message_id) else { return Ok(()) };
-            get_webhook(&tracked.message.channel_id)?
-                .delete(ctx, &tracked.response)
-                .await?;
+            for link in data.message_links(*message_id)? {
+                let (response_id, thread_id) = (link.response_id, link.thread_id);
+                let result = match get_webhook(&link.channel_id) {
+                    Ok(webhook) => {
+                        data.with_webhook(ctx, &webhook, |webhook| async move {
+                            webhook.delete(ctx, response_id, thread_id).await
+                        })
+                        .await
+                    }
+                    Err(err) => Err(err),
+                };
+                // The other translations are deleted anyway, e.g. if one already was
+                if let Err(err) = result {
+                    warn!("Failed to delete translation {}: {}", response_id, err);
+                }
+            }
+            data.database.delete_links(*message_id)?;
+        }
+
+        ReactionAdd {
+            add_reaction: reaction,
+        } => {
+            let ReactionType::Unicode(emoji) = &reaction.emoji else { return Ok(()) };
+            let Some(target) = Language::from_flag(emoji) else { return Ok(()) };
+            let (Some(guild_id), Some(user_id)) = (reaction.guild_id, reaction.user_id) else {
+                return Ok(());
+            };
+            if user_id == ctx.cache.current_user().id
+                || !data.database.flag_reactions(guild_id)?
+                || is_paused(Some(guild_id))?
+            {
+                return Ok(());
+            }
+
+            // Users with a language of their own get translations privately, in their language
+            let (target, recipient) = match data.database.preference(user_id)? {
+                Some(preference) => (preference.language, Some(user_id)),
+                None => (target, None),
+            };
+            let private = recipient.is_some();
+            if !data.claim_reaction(reaction.message_id, target, recipient) {
+                return Ok(());
+            }
+
+            let result = translate_reaction(ctx, framework, data, reaction, target, private).await;
+            if result.is_err() {
+                data.release_reaction(reaction.message_id, target, recipient);
+            }
+            result?;
         }
         _ => (),
     }
 
     Ok(())
 }
+
+/// Whether `update` changes the content or the rich embeds of the message
+///
+/// Discord also sends updates when it adds link previews, which are never translated. Without
+/// the previous version of the message in the cache, any content or rich embed is a change.
+fn changes_translation(old: Option<&serenity::Message>, update: &MessageUpdateEvent) -> bool {
+    let content_changed = update
+        .content
+        .as_ref()
+        .is_some_and(|content| old.is_none_or(|old| old.content != *content));
+    let embeds_changed = update.embeds.as_ref().is_some_and(|embeds| match old {
+        Some(old) => rich_embeds(embeds) != rich_embeds(&old.embeds),
+        None => !rich_embeds(embeds).is_empty(),
+    });
+
+    content_changed || embeds_changed
+}
+
+/// Embeds posted by bots as comparable JSON, leaving out link previews
+fn rich_embeds(embeds: &[Embed]) -> Vec<serenity::json::Value> {
+    embeds
+        .iter()
+        .filter(|embed| embed.kind.as_deref() == Some("rich"))
+        .filter_map(|embed| serenity::json::to_value(embed).ok())
+        .collect()
+}
+
+/// Translates the message `reaction` is on into `target`
+///
+/// The translation is sent to the user who reacted if `private`, else posted in the channel.
+async fn translate_reaction(
+    ctx: &Context,
+    framework: FrameworkContext<'_, Data, Error>,
+    data: &Data,
+    reaction: &Reaction,
+    target: &'static Language,
+    private: bool,
+) -> Result<()> {
+    let mut message = reaction.message(ctx).await?;
+    message.guild_id = message.guild_id.or(reaction.guild_id);
+    // Translations and other relayed messages
+    if message.webhook_id.is_some() || message.author.id == ctx.cache.current_user().id {
+        return Ok(());
+    }
+    if !private && data.opted_out(message.author.id, message.guild_id)? {
+        return Ok(());
+    }
+
+    let mut settings = data.channel_settings(message.channel_id);
+    settings.translate_embeds = true;
+    settings.translate_attachments = true;
+    if !settings.languages.contains(&target) {
+        settings.languages.push(target);
+    }
+    let translation = match Translation::new(ctx, data, &message, &settings).await {
+        Err(err) if err.is_skip() => {
+            debug!("Not translating {} for a reaction: {}", message.id, err);
+            return Ok(());
+        }
+        result => result?,
+    };
+    if translation.detection.language == target {
+        return Ok(());
+    }
+
+    match private {
+        true => {
+            let user_id = reaction.user_id.context("Unknown user")?;
+            let translated = translation.translate(target).await?;
+            user_id
+                .create_dm_channel(ctx)
+                .await?
+                .send_message(ctx, translated.into_message(target))
+                .await?;
+            // Keeps the translation private, needs the permission to manage messages
+            if let Err(err) = reaction.delete(ctx).await {
+                debug!("Failed to remove the reaction on {}: {}", message.id, err);
+            }
+        }
+        false => {
+            let webhook = data.webhook(ctx, message.channel_id).await?;
+            let (message, translation) = (&message, &translation);
+            data.with_webhook(ctx, &webhook, |webhook| async move {
+                webhook
+                    .translate_reply(ctx, &framework, message, translation, target)
+                    .await
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
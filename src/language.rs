@@ -0,0 +1,164 @@
+use crate::translator::Translator;
+
+use anyhow::{bail, Result};
+
+#[derive(Debug)]
+pub struct Language {
+    /// Lowercase ISO 639-1 code, as used by [`crate::translator::Translator`]
+    pub code: &'static str,
+    pub name: &'static str,
+    pub flag: &'static str,
+    pub whatlang: whatlang::Lang,
+}
+
+impl Language {
+    pub fn from_code(code: &str) -> Option<&'static Self> {
+        let code = code.trim().to_lowercase();
+        LANGUAGES.iter().find(|language| language.code == code)
+    }
+
+    /// The language of a country's flag emoji, e.g. 🇫🇷 or 🇧🇷
+    pub fn from_flag(flag: &str) -> Option<&'static Self> {
+        LANGUAGES
+            .iter()
+            .find(|language| language.flag == flag)
+            .or_else(|| {
+                let (_, code) = FLAGS.iter().find(|(other, _)| *other == flag)?;
+                Self::from_code(code)
+            })
+    }
+
+    pub fn from_whatlang(lang: whatlang::Lang) -> Option<&'static Self> {
+        LANGUAGES.iter().find(|language| language.whatlang == lang)
+    }
+}
+
+impl PartialEq for Language {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code
+    }
+}
+
+macro_rules! languages {
+    ($(($code:literal, $name:literal, $flag:literal, $whatlang:ident);)+) => {
+        pub const LANGUAGES: &[Language] = &[
+            $(Language {
+                code: $code,
+                name: $name,
+                flag: $flag,
+                whatlang: whatlang::Lang::$whatlang,
+            },)+
+        ];
+    };
+}
+
+languages! {
+    ("en", "English",    "🇬🇧", Eng);
+    ("ja", "Japanese",   "🇯🇵", Jpn);
+    ("ar", "Arabic",     "🇸🇦", Ara);
+    ("bg", "Bulgarian",  "🇧🇬", Bul);
+    ("cs", "Czech",      "🇨🇿", Ces);
+    ("da", "Danish",     "🇩🇰", Dan);
+    ("de", "German",     "🇩🇪", Deu);
+    ("el", "Greek",      "🇬🇷", Ell);
+    ("es", "Spanish",    "🇪🇸", Spa);
+    ("et", "Estonian",   "🇪🇪", Est);
+    ("fi", "Finnish",    "🇫🇮", Fin);
+    ("fr", "French",     "🇫🇷", Fra);
+    ("hu", "Hungarian",  "🇭🇺", Hun);
+    ("id", "Indonesian", "🇮🇩", Ind);
+    ("it", "Italian",    "🇮🇹", Ita);
+    ("ko", "Korean",     "🇰🇷", Kor);
+    ("lt", "Lithuanian", "🇱🇹", Lit);
+    ("lv", "Latvian",    "🇱🇻", Lav);
+    ("nb", "Norwegian",  "🇳🇴", Nob);
+    ("nl", "Dutch",      "🇳🇱", Nld);
+    ("pl", "Polish",     "🇵🇱", Pol);
+    ("pt", "Portuguese", "🇵🇹", Por);
+    ("ro", "Romanian",   "🇷🇴", Ron);
+    ("ru", "Russian",    "🇷🇺", Rus);
+    ("sk", "Slovak",     "🇸🇰", Slk);
+    ("sl", "Slovenian",  "🇸🇮", Slv);
+    ("sv", "Swedish",    "🇸🇪", Swe);
+    ("tr", "Turkish",    "🇹🇷", Tur);
+    ("uk", "Ukrainian",  "🇺🇦", Ukr);
+    ("zh", "Chinese",    "🇨🇳", Cmn);
+}
+
+/// Flags of other countries mainly speaking a language, countries with several are left out
+const FLAGS: &[(&str, &str)] = &[
+    ("🇺🇸", "en"),
+    ("🇦🇺", "en"),
+    ("🇳🇿", "en"),
+    ("🇮🇪", "en"),
+    ("🇦🇪", "ar"),
+    ("🇪🇬", "ar"),
+    ("🇯🇴", "ar"),
+    ("🇲🇦", "ar"),
+    ("🇦🇹", "de"),
+    ("🇱🇮", "de"),
+    ("🇨🇾", "el"),
+    ("🇦🇷", "es"),
+    ("🇨🇱", "es"),
+    ("🇨🇴", "es"),
+    ("🇲🇽", "es"),
+    ("🇵🇪", "es"),
+    ("🇻🇪", "es"),
+    ("🇳🇴", "nb"),
+    ("🇸🇷", "nl"),
+    ("🇦🇴", "pt"),
+    ("🇧🇷", "pt"),
+    ("🇲🇩", "ro"),
+    ("🇹🇼", "zh"),
+];
+
+/// Parses a comma separated list of language codes, e.g. `en,ja,ko`
+pub fn parse_list(list: &str) -> Result<Vec<&'static Language>> {
+    let mut languages = Vec::new();
+
+    for code in list.split(',').filter(|code| !code.trim().is_empty()) {
+        let Some(language) = Language::from_code(code) else {
+            bail!("Unknown language `{}`", code.trim());
+        };
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+
+    if languages.len() < 2 {
+        bail!("At least two languages are required");
+    }
+
+    Ok(languages)
+}
+
+/// Fails unless `translator` can translate from and into all of `languages`
+pub async fn check_supported(translator: &dyn Translator, languages: &[&Language]) -> Result<()> {
+    let supported = translator.supported_languages().await?;
+
+    if let Some(language) = languages
+        .iter()
+        .find(|language| !supported.iter().any(|code| code == language.code))
+    {
+        bail!("{} isn't supported by the translator", language.name);
+    }
+
+    Ok(())
+}
+
+pub fn format_list(languages: &[&Language]) -> String {
+    languages
+        .iter()
+        .map(|language| language.code)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Human readable list, e.g. `English, Japanese`
+pub fn describe_list(languages: &[&Language]) -> String {
+    languages
+        .iter()
+        .map(|language| language.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
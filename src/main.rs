@@ -1,11 +1,17 @@
 mod commands;
 mod config;
 mod data;
+mod database;
+mod error;
 mod error_handler;
 mod event_handler;
+mod language;
+mod markup;
+mod metrics;
 mod options;
 mod setup;
 mod translation;
+mod translator;
 mod webhook;
 
 use error_handler::error_handler;
@@ -26,10 +32,14 @@ use tracing_subscriber::EnvFilter;
 use poise::serenity_prelude as serenity;
 use poise::Framework;
 use secrecy::ExposeSecret;
+use serenity::cache::Settings as CacheSettings;
 use serenity::{ClientBuilder, GatewayIntents};
 
 type Context<'a> = poise::Context<'a, Data, Error>;
 
+/// Messages kept in the cache per channel
+const MAX_CACHED_MESSAGES: usize = 50;
+
 async fn run() -> Result<()> {
     debug!("Loading secrets");
     let config = Config::new()?;
@@ -42,10 +52,18 @@ async fn run() -> Result<()> {
         .build();
 
     use GatewayIntents as Intents;
-    let intents = Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT;
+    let intents = Intents::GUILDS
+        | Intents::GUILD_MESSAGES
+        | Intents::GUILD_MESSAGE_REACTIONS
+        | Intents::MESSAGE_CONTENT;
+
+    // Recent messages tell edits apart from updates adding link previews
+    let mut cache_settings = CacheSettings::default();
+    cache_settings.max_messages = MAX_CACHED_MESSAGES;
 
     debug!("Starting up");
     ClientBuilder::new(token.expose_secret(), intents)
+        .cache_settings(cache_settings)
         .framework(framework)
         .await?
         .start()
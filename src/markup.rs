@@ -0,0 +1,375 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+/// Paired inline formatting, longest first so `**` isn't read as two `*`
+const DELIMITERS: &[&str] = &["||", "**", "__", "~~", "*", "_"];
+
+lazy_static! {
+    static ref CODE_BLOCK_REGEX: Regex = Regex::new(r"(?s)```.*?```").unwrap();
+    /// Syntax at the start of a line: quotes, headers, subtext and list items
+    static ref LINE_PREFIX_REGEX: Regex =
+        Regex::new(r"^(?:>>> |> |#{1,3} |-# |[ \t]*(?:[-*]|\d+\.) )").unwrap();
+    /// Inline spans kept verbatim: escapes, code, custom emoji, mentions, timestamps and URLs
+    static ref PROTECTED_REGEX: Regex = Regex::new(concat!(
+        r"^(?:\\[^\w\s]|``[^`]+``|`[^`]+`",
+        r"|<a?:\w+:\d+>",
+        r"|<(?:@[!&]?|#)\d+>|</[\w -]+:\d+>|@(?:everyone|here)",
+        r"|<t:-?\d+(?::\w)?>",
+        r"|<https?://[^>\s]+>|https?://\S+)",
+    ))
+    .unwrap();
+    static ref MASKED_LINK_REGEX: Regex =
+        Regex::new(r"^\[([^\]\n]+)\]\(<?https?://[^)\s]+>?\)").unwrap();
+    static ref TAG_REGEX: Regex = Regex::new(r#"<x i="(\d+)"\s*/>|<m i="(\d+)">|</m>"#).unwrap();
+}
+
+/// A piece of Discord flavoured markdown
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    /// Prose to translate
+    Text(&'a str),
+    /// Syntax or content kept verbatim, e.g. code, mentions or list markers
+    Protected(&'a str),
+    /// Start of formatting around translatable segments, e.g. `**` or the `[` of a masked link
+    Open(&'a str),
+    /// End of the formatting last opened, e.g. `**` or the `](url)` of a masked link
+    Close(&'a str),
+}
+
+/// Splits `content` into segments, [`Segment::Open`] and [`Segment::Close`] are always paired
+pub fn parse(content: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut last = 0;
+
+    for code_block in CODE_BLOCK_REGEX.find_iter(content) {
+        let at_line_start = last == 0 || content[..last].ends_with('\n');
+        lines(
+            &content[last..code_block.start()],
+            at_line_start,
+            &mut segments,
+        );
+        segments.push(Segment::Protected(code_block.as_str()));
+        last = code_block.end();
+    }
+    let at_line_start = last == 0 || content[..last].ends_with('\n');
+    lines(&content[last..], at_line_start, &mut segments);
+
+    segments
+}
+
+fn lines<'a>(text: &'a str, at_line_start: bool, segments: &mut Vec<Segment<'a>>) {
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let (mut line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+
+        if index > 0 || at_line_start {
+            while let Some(prefix) = LINE_PREFIX_REGEX.find(line) {
+                segments.push(Segment::Protected(prefix.as_str()));
+                line = &line[prefix.end()..];
+            }
+        }
+        inline(line, segments);
+
+        if !newline.is_empty() {
+            segments.push(Segment::Text(newline));
+        }
+    }
+}
+
+fn inline<'a>(text: &'a str, segments: &mut Vec<Segment<'a>>) {
+    let mut text_start = 0;
+    let mut index = 0;
+
+    while index < text.len() {
+        let rest = &text[index..];
+        let flush = |segments: &mut Vec<Segment<'a>>| {
+            if text_start < index {
+                segments.push(Segment::Text(&text[text_start..index]));
+            }
+        };
+
+        if let Some(protected) = PROTECTED_REGEX.find(rest) {
+            flush(segments);
+            segments.push(Segment::Protected(protected.as_str()));
+            index += protected.end();
+            text_start = index;
+            continue;
+        }
+
+        if let Some(link) = MASKED_LINK_REGEX.captures(rest) {
+            let label = link.get(1).unwrap();
+            flush(segments);
+            segments.push(Segment::Open("["));
+            inline(label.as_str(), segments);
+            segments.push(Segment::Close(&rest[label.end()..link[0].len()]));
+            index += link[0].len();
+            text_start = index;
+            continue;
+        }
+
+        let after_word = text[..index]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric);
+        let formatted = DELIMITERS.iter().find_map(|delimiter| {
+            let inner = rest.strip_prefix(delimiter)?;
+            if delimiter.starts_with('_') && after_word {
+                return None;
+            }
+            let end = inner.find(delimiter).filter(|end| *end > 0)?;
+            let inner = &inner[..end];
+            // Italics can't start or end with a space, e.g. `3 * 4 * 5`
+            if delimiter.len() == 1 && (inner.starts_with(' ') || inner.ends_with(' ')) {
+                return None;
+            }
+            Some((*delimiter, inner))
+        });
+        if let Some((delimiter, inner)) = formatted {
+            flush(segments);
+            segments.push(Segment::Open(delimiter));
+            inline(inner, segments);
+            segments.push(Segment::Close(delimiter));
+            index += delimiter.len() * 2 + inner.len();
+            text_start = index;
+            continue;
+        }
+
+        index += rest.chars().next().map_or(1, char::len_utf8);
+    }
+
+    if text_start < text.len() {
+        segments.push(Segment::Text(&text[text_start..]));
+    }
+}
+
+/// Message content prepared for translation
+///
+/// The content is turned into an XML document, so the translator moves the markup along with
+/// the text around it without translating or billing it. Protected segments are replaced by
+/// `<x i="0"/>` placeholders and formatting by `<m i="0">…</m>` elements.
+pub struct Markup {
+    pub xml: String,
+    /// The text to translate without the markup, for language detection
+    pub prose: String,
+    /// Protected segments, and the opening and closing syntax of each formatting element
+    spans: Vec<String>,
+}
+
+impl Markup {
+    pub fn new(content: &str) -> Self {
+        let mut xml = String::new();
+        let mut prose = String::new();
+        let mut spans = Vec::new();
+        let mut open = Vec::new();
+
+        for segment in parse(content) {
+            match segment {
+                Segment::Text(text) => {
+                    xml.push_str(&escape(text));
+                    prose.push_str(text);
+                }
+                Segment::Protected(protected) => {
+                    xml.push_str(&format!(r#"<x i="{}"/>"#, spans.len()));
+                    spans.push(protected.to_string());
+                    prose.push(' ');
+                }
+                Segment::Open(syntax) => {
+                    xml.push_str(&format!(r#"<m i="{}">"#, spans.len()));
+                    open.push(spans.len() + 1);
+                    spans.extend([syntax.to_string(), String::new()]);
+                }
+                Segment::Close(syntax) => {
+                    xml.push_str("</m>");
+                    if let Some(index) = open.pop() {
+                        spans[index] = syntax.to_string();
+                    }
+                }
+            }
+        }
+
+        Self {
+            xml,
+            prose: prose.trim().to_string(),
+            spans,
+        }
+    }
+
+    /// Puts the markup back into a translation of [`Markup::xml`]
+    pub fn restore(&self, translated: &str) -> String {
+        let mut restored = String::new();
+        let mut open = Vec::new();
+        let mut last = 0;
+
+        for tag in TAG_REGEX.captures_iter(translated) {
+            let whole = tag.get(0).unwrap();
+            restored.push_str(&unescape(&translated[last..whole.start()]));
+            last = whole.end();
+
+            if let Some(index) = span_index(&tag, 1) {
+                restored.push_str(self.span(index));
+            } else if let Some(index) = span_index(&tag, 2) {
+                restored.push_str(self.span(index));
+                open.push(index + 1);
+            } else if let Some(index) = open.pop() {
+                restored.push_str(self.span(index));
+            }
+        }
+        restored.push_str(&unescape(&translated[last..]));
+
+        restored
+    }
+
+    pub fn has_spoilers(&self) -> bool {
+        self.spans.iter().any(|span| span == "||")
+    }
+
+    fn span(&self, index: usize) -> &str {
+        self.spans.get(index).map_or("", String::as_str)
+    }
+}
+
+fn span_index(tag: &Captures<'_>, group: usize) -> Option<usize> {
+    tag.get(group)?.as_str().parse().ok()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Segment::{Close, Open, Protected, Text};
+
+    #[test]
+    fn parses_nested_formatting() {
+        assert_eq!(
+            parse("**bold *italic* ||spoiler||**"),
+            [
+                Open("**"),
+                Text("bold "),
+                Open("*"),
+                Text("italic"),
+                Close("*"),
+                Text(" "),
+                Open("||"),
+                Text("spoiler"),
+                Close("||"),
+                Close("**"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_code_blocks() {
+        assert_eq!(
+            parse("```a``` and ```b```"),
+            [Protected("```a```"), Text(" and "), Protected("```b```")]
+        );
+        assert_eq!(
+            parse("> quoted\n```\n> code\n```\n> quoted"),
+            [
+                Protected("> "),
+                Text("quoted"),
+                Text("\n"),
+                Protected("```\n> code\n```"),
+                Text("\n"),
+                Protected("> "),
+                Text("quoted"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_line_prefixes() {
+        assert_eq!(
+            parse("# Title\n>>> quote\n- item\n1. first\n-# small"),
+            [
+                Protected("# "),
+                Text("Title"),
+                Text("\n"),
+                Protected(">>> "),
+                Text("quote"),
+                Text("\n"),
+                Protected("- "),
+                Text("item"),
+                Text("\n"),
+                Protected("1. "),
+                Text("first"),
+                Text("\n"),
+                Protected("-# "),
+                Text("small"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_masked_links() {
+        assert_eq!(
+            parse("see [the **docs**](<https://example.com>) here"),
+            [
+                Text("see "),
+                Open("["),
+                Text("the "),
+                Open("**"),
+                Text("docs"),
+                Close("**"),
+                Close("](<https://example.com>)"),
+                Text(" here"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_inline_syntax() {
+        assert_eq!(
+            parse("hi <@123> `code` <:emoji:456> https://example.com snake_case_name"),
+            [
+                Text("hi "),
+                Protected("<@123>"),
+                Text(" "),
+                Protected("`code`"),
+                Text(" "),
+                Protected("<:emoji:456>"),
+                Text(" "),
+                Protected("https://example.com"),
+                Text(" snake_case_name"),
+            ]
+        );
+    }
+
+    #[test]
+    fn restores_echoed_markup() {
+        for content in [
+            "**bold *italic* ||spoiler||**",
+            "```a``` and ```b```\n> quoted\n```\ncode\n```",
+            "# Title\n- [the **docs**](<https://example.com>) <@123>",
+            "3 * 4 * 5 < 6 && 7 > 2",
+            "\\*not italic\\* `a < b`",
+        ] {
+            let markup = Markup::new(content);
+            assert_eq!(markup.restore(&markup.xml), content);
+        }
+    }
+
+    #[test]
+    fn restores_reordered_markup() {
+        let markup = Markup::new("**Hello** <@123>");
+        assert_eq!(
+            markup.restore(r#"<x i="2"/> <m i="0">こんにちは</m>"#),
+            "<@123> **こんにちは**"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::database::Database;
+
+use anyhow::Result;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+
+lazy_static! {
+    pub static ref TRANSLATIONS: IntCounterVec = register_int_counter_vec!(
+        "translate_bot_translations_total",
+        "Translations performed, including cached ones",
+        &["target"]
+    )
+    .unwrap();
+    pub static ref BILLED_CHARACTERS: IntCounter = register_int_counter!(
+        "translate_bot_billed_characters_total",
+        "Characters counted against the translator's limit"
+    )
+    .unwrap();
+    pub static ref DEEPL_DURATION: Histogram = register_histogram!(
+        "translate_bot_deepl_request_duration_seconds",
+        "Latency of DeepL translation requests"
+    )
+    .unwrap();
+    pub static ref DEEPL_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "translate_bot_deepl_errors_total",
+        "Failed DeepL translation requests by HTTP status or failure",
+        &["class"]
+    )
+    .unwrap();
+    pub static ref WEBHOOK_DURATION: HistogramVec = register_histogram_vec!(
+        "translate_bot_webhook_request_duration_seconds",
+        "Latency of webhook requests",
+        &["operation"]
+    )
+    .unwrap();
+    pub static ref TRACKED_MESSAGES: IntGauge = register_int_gauge!(
+        "translate_bot_tracked_messages",
+        "Messages whose translations are still edited and deleted along with them"
+    )
+    .unwrap();
+    pub static ref ENABLED_CHANNELS: IntGauge = register_int_gauge!(
+        "translate_bot_autotranslate_channels",
+        "Channels with automatic translation enabled"
+    )
+    .unwrap();
+    pub static ref GATEWAY_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "translate_bot_gateway_events_total",
+        "Gateway events handled",
+        &["event"]
+    )
+    .unwrap();
+}
+
+/// Serves the metrics in the Prometheus text format on every path of `address`
+pub async fn serve(address: SocketAddr, database: Arc<Database>) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let database = database.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_| {
+                let response = respond(&database);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)?.serve(make_service);
+    info!("Serving metrics on {}", address);
+    server.await?;
+
+    Ok(())
+}
+
+fn respond(database: &Database) -> Response<Body> {
+    match database.tracked_message_count() {
+        Ok(count) => TRACKED_MESSAGES.set(count),
+        Err(err) => error!("Failed to count tracked messages: {:#}", err),
+    }
+    match database.channel_count() {
+        Ok(count) => ENABLED_CHANNELS.set(count),
+        Err(err) => error!("Failed to count autotranslate channels: {:#}", err),
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(err) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", err);
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return response;
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    if let Ok(content_type) = encoder.format_type().parse() {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+}
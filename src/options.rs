@@ -1,9 +1,14 @@
 use crate::{error_handler, event_handler, Data};
 
 use crate::commands::{
+    bridge::bridge,
+    glossary::glossary,
+    language::language,
     other::{cleanup, help},
     owner::{ping, register, servers, uptime},
-    translation::{autotranslate, translate, usage},
+    translation::{
+        autotranslate, budget, preferences, translate, translate_message, translate_to, usage,
+    },
 };
 
 use anyhow::{Error, Result};
@@ -26,8 +31,17 @@ pub fn options() -> Result<FrameworkOptions<Data, Error>> {
             uptime(),
             // Translation
             autotranslate(),
+            budget(),
+            language(),
+            preferences(),
             translate(),
+            translate_message(),
+            translate_to(),
             usage(),
+            // Bridge
+            bridge(),
+            // Glossary
+            glossary(),
             // Other
             cleanup(),
             help(),
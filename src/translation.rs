@@ -1,100 +1,813 @@
-use anyhow::{bail, Result};
+use crate::data::Settings;
+use crate::database::MessageLink;
+use crate::error::BotError;
+use crate::language::{Language, LANGUAGES};
+use crate::markup::Markup;
+use crate::translator::{self, Options, Request};
+use crate::{metrics, Data};
+
+use anyhow::Result;
+use tracing::error;
 
 use poise::serenity_prelude as serenity;
-use serenity::Message;
+use serenity::{
+    Attachment, ChannelId, CreateAttachment, CreateEmbed, CreateMessage, Embed, GetMessages,
+    GuildId, Message, MessageBuilder, MessageId, UserId,
+};
+
+use whatlang::Detector;
 
-use deepl::DeepLApi;
-use lazy_static::lazy_static;
-use regex::{Regex, RegexBuilder};
+const RELIABLE_CONFIDENCE: f64 = 0.9;
+/// Larger attachments are not translated
+const MAX_ATTACHMENT_SIZE: u32 = 16 * 1024; // 16 KiB
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md"];
+pub const MAX_CONTEXT_MESSAGES: u8 = 10;
+/// Longer messages are translated without context, they're usually clear enough on their own
+const MAX_CONTEXTUAL_LENGTH: usize = 200;
+/// Larger relayed attachments are linked instead of uploaded again
+const MAX_RELAYED_SIZE: u32 = 8 * 1024 * 1024; // 8 MiB
+const MAX_SNIPPET_LENGTH: usize = 60;
 
-pub struct TargetLanguage {
-    pub lang: deepl::Lang,
-    pub flag: &'static str,
+pub struct Translation<'a> {
+    data: &'a Data,
+    content: &'a str,
+    origin: Origin,
+    /// Embeds posted by bots, not link previews
+    embeds: Vec<Embed>,
+    /// Names and contents of text attachments
+    attachments: Vec<(String, String)>,
+    /// Surrounding messages that help translate short ones, not translated themselves
+    context: Option<String>,
+    relayed: Option<Relayed>,
+    pub detection: Detection,
+    /// Whether the text is relayed without translating it, as its language is unknown
+    verbatim: bool,
+    pub targets: Vec<&'static Language>,
+    options: Options,
 }
 
-impl TargetLanguage {
-    fn from_lang(lang: Lang) -> Self {
-        match lang {
-            Lang::JA => Self {
-                lang: deepl::Lang::EN,
-                flag: "🇬🇧",
-            },
-            Lang::EN => Self {
-                lang: deepl::Lang::JA,
-                flag: "🇯🇵",
-            },
+/// Who a translation is for and where, deciding the glossary, the options and who is billed
+#[derive(Clone, Copy)]
+pub struct Origin {
+    pub user_id: UserId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+}
+
+impl From<&Message> for Origin {
+    fn from(message: &Message) -> Self {
+        Self {
+            user_id: message.author.id,
+            guild_id: message.guild_id,
+            channel_id: message.channel_id,
         }
     }
 }
 
-lazy_static! {
-    static ref URL_REGEX: Regex = Regex::new(r"https?://\S+").unwrap();
-    static ref EVERYONE_REGEX: Regex = Regex::new(r"@(everyone|here)").unwrap();
-    static ref MENTION_REGEX: Regex = Regex::new(r"<(@&|@|#)\d+>").unwrap();
-    static ref EMOJI_REGEX: Regex = Regex::new(r"<:\w+:\d+>").unwrap();
-    static ref TIMESTAMP_REGEX: Regex = Regex::new(r"<t:\d+:?\w>").unwrap();
-    static ref CODE_BLOCK_REGEX: Regex = RegexBuilder::new(r"```.*```")
-        .dot_matches_new_line(true)
-        .build()
-        .unwrap();
+/// What's carried over from a message besides its translation
+struct Relayed {
+    /// Names and contents of attachments uploaded again
+    attachments: Vec<(String, Vec<u8>)>,
+    /// Attachments too large to upload again
+    urls: Vec<String>,
+    sticker_urls: Vec<String>,
+    reply: Option<Reply>,
 }
 
-pub struct Translation<'a> {
-    deepl: &'a DeepLApi,
-    message: &'a Message,
-    pub target: TargetLanguage,
+/// The message replied to, shown above the translation
+struct Reply {
+    message_id: MessageId,
+    /// The message itself, used when it has no translation to point to instead
+    url: String,
+    author: String,
+    snippet: String,
+}
+
+pub struct Detection {
+    pub language: &'static Language,
+    /// Between `0.0` and `1.0`
+    pub confidence: f64,
+}
+
+/// A message translated into one language
+pub struct Translated {
+    pub content: String,
+    pub embeds: Vec<CreateEmbed>,
+    pub attachments: Vec<CreateAttachment>,
+}
+
+impl Translated {
+    /// A message holding the translation into `target`, e.g. for direct messages
+    pub fn into_message(self, target: &Language) -> CreateMessage {
+        CreateMessage::new()
+            .content(format!("{} {}", target.flag, self.content))
+            .embeds(self.embeds)
+            .add_files(self.attachments)
+    }
 }
 
 impl<'a> Translation<'a> {
-    pub fn new(deepl: &'a DeepLApi, message: &'a Message) -> Result<Self> {
-        let mut without_matches = message.content.clone();
-
-        for regex in [
-            &*EVERYONE_REGEX,
-            &*MENTION_REGEX,
-            &*EMOJI_REGEX,
-            &*TIMESTAMP_REGEX,
-            &*URL_REGEX,
-            &*CODE_BLOCK_REGEX,
-        ] {
-            without_matches = regex.replace_all(&without_matches, "").into();
+    /// Prepares translating `message` from its detected language into the other languages
+    ///
+    /// Embeds and text attachments are included if enabled in `settings`.
+    pub async fn new(
+        ctx: &serenity::Context,
+        data: &'a Data,
+        message: &'a Message,
+        settings: &Settings,
+    ) -> Result<Self, BotError> {
+        let embeds = match settings.translate_embeds {
+            true => message
+                .embeds
+                .iter()
+                .filter(|embed| embed.kind.as_deref() == Some("rich"))
+                .cloned()
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let mut attachments = Vec::new();
+        let mut untranslated = Vec::new();
+        for attachment in &message.attachments {
+            if !settings.translate_attachments || !is_text(attachment) {
+                untranslated.push(attachment);
+                continue;
+            }
+            match attachment.download().await.map(String::from_utf8) {
+                Ok(Ok(text)) => attachments.push((attachment.filename.clone(), text)),
+                Ok(Err(_)) => untranslated.push(attachment),
+                Err(err) => error!("Failed to download {}: {}", attachment.url, err),
+            }
         }
-        without_matches = without_matches.trim().into();
+        let relayed = match settings.relay {
+            true => Some(Relayed::new(message, &untranslated).await),
+            false => None,
+        };
 
-        if without_matches.is_empty() | without_matches.chars().all(|c| !c.is_alphabetic()) {
-            bail!("Nothing to translate");
+        let mut texts = vec![message.content.as_str()];
+        for embed in &embeds {
+            texts.extend(embed_texts(embed));
         }
+        texts.extend(attachments.iter().map(|(_, text)| text.as_str()));
+        let prose = texts
+            .iter()
+            .map(|text| Markup::new(text).prose)
+            .collect::<Vec<_>>()
+            .join("\n");
 
-        let original = detect_language(&without_matches);
-        let target = TargetLanguage::from_lang(original);
+        let detection = match has_letters(&prose) {
+            true => match detect_language(&prose, &settings.languages) {
+                Some(detection) if detection.confidence >= settings.min_confidence => Ok(detection),
+                Some(_) => Err(BotError::NotConfident),
+                None => Err(BotError::UnknownLanguage),
+            },
+            false => Err(BotError::NothingToTranslate),
+        };
+        // Relayed as is, e.g. images, emoji or chatter like "ok", the language doesn't matter
+        let (detection, verbatim) = match detection {
+            Ok(detection) => (detection, false),
+            Err(_) if relayed.is_some() => {
+                let detection = Detection {
+                    language: settings
+                        .languages
+                        .first()
+                        .ok_or(BotError::NothingToTranslate)?,
+                    confidence: 0.0,
+                };
+                (detection, true)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let targets = settings
+            .languages
+            .iter()
+            .copied()
+            .filter(|language| *language != detection.language)
+            .collect();
+
+        let context = match settings.context_messages {
+            0 => None,
+            _ if verbatim => None,
+            _ if prose.chars().count() > MAX_CONTEXTUAL_LENGTH => None,
+            count => context(ctx, data, message, count).await,
+        };
+
+        Ok(Self {
+            data,
+            content: &message.content,
+            origin: Origin::from(message),
+            embeds,
+            attachments,
+            context,
+            relayed,
+            detection,
+            verbatim,
+            targets,
+            options: user_options(data, message.author.id).or(settings.options),
+        })
+    }
+
+    /// Prepares translating `text` into `target`, from `source` or else its detected language
+    ///
+    /// Text whose language can't be detected locally is left to the translator, which is worth
+    /// paying for when someone asked for the translation.
+    pub async fn from_text(
+        data: &'a Data,
+        text: &'a str,
+        origin: Origin,
+        source: Option<&'static Language>,
+        target: &'static Language,
+        settings: &Settings,
+    ) -> Result<Self, BotError> {
+        let prose = Markup::new(text).prose;
+        if !has_letters(&prose) {
+            return Err(BotError::NothingToTranslate);
+        }
+
+        let detection = match source {
+            Some(language) => Detection {
+                language,
+                confidence: 1.0,
+            },
+            None => match detect_language(&prose, &LANGUAGES.iter().collect::<Vec<_>>()) {
+                Some(detection) => detection,
+                None => Detection {
+                    language: Language::from_code(&data.translator.detect(&prose).await?)
+                        .ok_or(BotError::UnknownLanguage)?,
+                    confidence: 1.0,
+                },
+            },
+        };
+        if detection.language == target {
+            return Err(BotError::AlreadyInLanguage(target));
+        }
 
         Ok(Self {
-            deepl,
-            message,
-            target,
+            data,
+            content: text,
+            origin,
+            embeds: Vec::new(),
+            attachments: Vec::new(),
+            context: None,
+            relayed: None,
+            detection,
+            verbatim: false,
+            targets: vec![target],
+            options: user_options(data, origin.user_id).or(settings.options),
         })
     }
 
-    pub async fn translate(&self) -> Option<String> {
-        self.deepl
-            .translate_text(&self.message.content, self.target.lang.clone())
+    pub fn has_embeds(&self) -> bool {
+        let has_stickers = self
+            .relayed
+            .as_ref()
+            .is_some_and(|relayed| !relayed.sticker_urls.is_empty());
+
+        !self.embeds.is_empty() || has_stickers
+    }
+
+    pub async fn translate(&self, target: &Language) -> Result<Translated, BotError> {
+        let mut content = self.translate_text(self.content, target).await?;
+        if let Some(relayed) = &self.relayed {
+            if let Some(reply) = &relayed.reply {
+                content = format!("{}\n{}", self.reply_header(reply, target), content);
+            }
+            for url in &relayed.urls {
+                content.push_str(&format!("\n{}", url));
+            }
+        }
+
+        let mut embeds = Vec::new();
+        for embed in &self.embeds {
+            let mut embed = embed.clone();
+            for text in embed_texts_mut(&mut embed) {
+                *text = self.translate_text(text, target).await?;
+            }
+            embeds.push(CreateEmbed::from(embed));
+        }
+
+        let mut attachments = Vec::new();
+        for (filename, text) in &self.attachments {
+            let translated = self.translate_text(text, target).await?;
+            let filename = match filename.rsplit_once('.') {
+                Some((name, extension)) => format!("{}.{}.{}", name, target.code, extension),
+                None => format!("{}.{}", filename, target.code),
+            };
+            attachments.push(CreateAttachment::bytes(translated, filename));
+        }
+
+        if let Some(relayed) = &self.relayed {
+            for url in &relayed.sticker_urls {
+                embeds.push(CreateEmbed::new().image(url));
+            }
+            for (filename, bytes) in &relayed.attachments {
+                attachments.push(CreateAttachment::bytes(bytes.clone(), filename));
+            }
+        }
+
+        Ok(Translated {
+            content,
+            embeds,
+            attachments,
+        })
+    }
+
+    /// Translates `text`, leaving it as is if it's relayed verbatim, already in `target` or
+    /// there's nothing but markup to translate
+    async fn translate_text(
+        &self,
+        text: &str,
+        target: &Language,
+    ) -> Result<String, translator::Error> {
+        let markup = Markup::new(text);
+        if self.verbatim || *target == *self.detection.language || !has_letters(&markup.prose) {
+            return Ok(text.to_string());
+        }
+
+        let glossary = self.glossary(target);
+        let source = match glossary {
+            Some(_) => Some(self.detection.language.code),
+            None => self.source(),
+        };
+
+        self.data
+            .translator
+            .translate(&Request {
+                text: &markup.xml,
+                xml: true,
+                characters: markup.prose.chars().count() as u64,
+                source,
+                target: target.code,
+                glossary: glossary.as_deref(),
+                context: self.context.as_deref(),
+                options: self.options,
+            })
             .await
-            .map(|translation| translation.to_string())
-            .ok()
+            .map(|response| {
+                metrics::TRANSLATIONS
+                    .with_label_values(&[target.code])
+                    .inc();
+                metrics::BILLED_CHARACTERS.inc_by(response.billed_characters);
+                if let Err(err) = self
+                    .data
+                    .record_usage(&self.origin, response.billed_characters)
+                {
+                    error!(
+                        "Failed to record usage in {}: {:#}",
+                        self.origin.channel_id, err
+                    );
+                }
+                markup.restore(&response.text)
+            })
+            .inspect_err(|err| {
+                if matches!(err, translator::Error::QuotaExceeded) {
+                    self.data.pause_for_quota();
+                }
+            })
+    }
+
+    /// A line pointing at the message replied to, or its translation into `target` if there's one
+    fn reply_header(&self, reply: &Reply, target: &Language) -> String {
+        let url = self
+            .reply_url(reply.message_id, target)
+            .unwrap_or_else(|err| {
+                error!("Failed to get the links of {}: {:#}", reply.message_id, err);
+                None
+            });
+
+        let header = format!(
+            "-# ↪ [Reply to @{}](<{}>)",
+            reply.author,
+            url.as_deref().unwrap_or(&reply.url)
+        );
+        match reply.snippet.is_empty() {
+            true => header,
+            false => format!("{}: {}", header, reply.snippet),
+        }
+    }
+
+    /// Where the counterpart of `message_id` is in the destination of a bridge
+    ///
+    /// That's its translation into `target`, or the original if it's a translation itself.
+    fn reply_url(&self, message_id: MessageId, target: &Language) -> Result<Option<String>> {
+        let translation = self
+            .data
+            .message_links(message_id)?
+            .into_iter()
+            .find(|link| link.target_lang == target.code);
+        if let Some(link) = translation {
+            return Ok(Some(self.response_url(&link)));
+        }
+
+        let original = self.data.database.link_of_response(message_id)?;
+        Ok(original.map(|link| {
+            let guild_id = self
+                .data
+                .bridges
+                .get(&link.channel_id)
+                .and_then(|bridge| bridge.guild_id);
+            link.message_id.link(link.channel_id, guild_id)
+        }))
+    }
+
+    /// The url of the translation `link` points to
+    fn response_url(&self, link: &MessageLink) -> String {
+        let (channel_id, guild_id) = match self.data.bridge_peer(link.channel_id) {
+            Some((peer_id, _)) => (
+                peer_id,
+                self.data
+                    .bridges
+                    .get(&peer_id)
+                    .and_then(|bridge| bridge.guild_id),
+            ),
+            None => (link.channel_id, self.origin.guild_id),
+        };
+
+        link.response_id
+            .link(link.thread_id.unwrap_or(channel_id), guild_id)
+    }
+
+    /// The guild's glossary from the detected language to `target`
+    fn glossary(&self, target: &Language) -> Option<String> {
+        let guild_id = self.origin.guild_id?;
+        let source = self.detection.language.code;
+
+        self.data
+            .database
+            .glossary(guild_id, source, target.code)
+            .unwrap_or_else(|err| {
+                error!("Failed to get the glossary of {}: {:#}", guild_id, err);
+                None
+            })?
+            .glossary_id
+    }
+
+    /// The source language, if the detection is reliable enough to not let the translator guess
+    fn source(&self) -> Option<&'static str> {
+        (self.detection.confidence >= RELIABLE_CONFIDENCE).then_some(self.detection.language.code)
+    }
+}
+
+impl Relayed {
+    async fn new(message: &Message, attachments: &[&Attachment]) -> Self {
+        let mut relayed = Self {
+            attachments: Vec::new(),
+            urls: Vec::new(),
+            sticker_urls: message
+                .sticker_items
+                .iter()
+                .filter_map(|sticker| sticker.image_url())
+                .collect(),
+            reply: message.referenced_message.as_deref().map(Reply::new),
+        };
+
+        for attachment in attachments {
+            if attachment.size > MAX_RELAYED_SIZE {
+                relayed.urls.push(attachment.url.clone());
+                continue;
+            }
+            match attachment.download().await {
+                Ok(bytes) => relayed
+                    .attachments
+                    .push((attachment.filename.clone(), bytes)),
+                Err(err) => {
+                    error!("Failed to download {}: {}", attachment.url, err);
+                    relayed.urls.push(attachment.url.clone());
+                }
+            }
+        }
+
+        relayed
+    }
+}
+
+impl Reply {
+    /// The snippet is cut from the text without its markup, so no formatting is left open, and
+    /// left out if the message has spoilers
+    fn new(message: &Message) -> Self {
+        let author = &message.author;
+        let markup = Markup::new(&message.content);
+        let snippet = match markup.has_spoilers() {
+            true => String::new(),
+            false => {
+                let line = markup.prose.lines().next().unwrap_or_default();
+                let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+                let mut snippet = line.chars().take(MAX_SNIPPET_LENGTH).collect::<String>();
+                if snippet.len() < line.len() || markup.prose.contains('\n') {
+                    snippet.push('…');
+                }
+                escape_markdown(&snippet)
+            }
+        };
+
+        Self {
+            message_id: message.id,
+            url: message.link(),
+            author: escape_markdown(author.global_name.as_ref().unwrap_or(&author.name)),
+            snippet,
+        }
     }
 }
 
-enum Lang {
-    EN,
-    JA,
+/// Escapes `text` to be shown as is, also in the text of a link
+fn escape_markdown(text: &str) -> String {
+    let escaped = MessageBuilder::new().push_safe(text).build();
+
+    ['~', '|', '[', ']']
+        .into_iter()
+        .fold(escaped, |escaped, special| {
+            escaped.replace(special, &format!("\\{}", special))
+        })
+}
+
+fn has_letters(text: &str) -> bool {
+    text.chars().any(char::is_alphabetic)
+}
+
+/// Options overridden by `user_id`
+fn user_options(data: &Data, user_id: UserId) -> Options {
+    data.database.user_options(user_id).unwrap_or_else(|err| {
+        error!("Failed to get the options of {}: {:#}", user_id, err);
+        Options::default()
+    })
+}
+
+/// The message `message` replies to and the `count` messages before it, oldest first
+///
+/// Translations are left out, they'd only repeat the other messages in another language.
+async fn context(
+    ctx: &serenity::Context,
+    data: &Data,
+    message: &Message,
+    count: u8,
+) -> Option<String> {
+    let history = message
+        .channel_id
+        .messages(
+            ctx,
+            GetMessages::new()
+                .before(message.id)
+                .limit(count.min(MAX_CONTEXT_MESSAGES)),
+        )
+        .await
+        .unwrap_or_else(|err| {
+            error!(
+                "Failed to get the history of {}: {}",
+                message.channel_id, err
+            );
+            Vec::new()
+        });
+
+    let referenced = message
+        .referenced_message
+        .as_deref()
+        .filter(|referenced| history.iter().all(|past| past.id != referenced.id));
+    let context = referenced
+        .into_iter()
+        .chain(history.iter().rev())
+        .filter(|past| {
+            past.webhook_id
+                .is_none_or(|webhook_id| !data.is_own_webhook(past.channel_id, webhook_id))
+        })
+        .map(|past| Markup::new(&past.content).prose)
+        .filter(|prose| !prose.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (!context.is_empty()).then_some(context)
+}
+
+fn is_text(attachment: &Attachment) -> bool {
+    let extension = attachment
+        .filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+
+    attachment.size <= MAX_ATTACHMENT_SIZE
+        && extension.is_some_and(|extension| TEXT_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// The texts of `embed` that are translated
+fn embed_texts(embed: &Embed) -> Vec<&str> {
+    let mut texts = embed
+        .title
+        .iter()
+        .chain(embed.description.iter())
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    for field in &embed.fields {
+        texts.extend([field.name.as_str(), field.value.as_str()]);
+    }
+    if let Some(footer) = &embed.footer {
+        texts.push(&footer.text);
+    }
+
+    texts
+}
+
+/// The texts of `embed` that are translated, to replace them with their translations
+fn embed_texts_mut(embed: &mut Embed) -> Vec<&mut String> {
+    let mut texts = embed
+        .title
+        .iter_mut()
+        .chain(embed.description.iter_mut())
+        .collect::<Vec<_>>();
+    for field in &mut embed.fields {
+        texts.extend([&mut field.name, &mut field.value]);
+    }
+    if let Some(footer) = &mut embed.footer {
+        texts.push(&mut footer.text);
+    }
+
+    texts
 }
 
-fn detect_language(text: &str) -> Lang {
-    let ascii_count = text.chars().filter(|c| c.is_ascii_alphanumeric()).count();
+/// Detects the language of `text` among the `expected` ones, or else the supported ones
+///
+/// Short text is easily mistaken for a language using the same script, e.g. English for Spanish,
+/// so other languages are only considered if the script of `text` isn't used by any expected
+/// one. Chinese is then left out when Japanese is expected, as text with only kanji is otherwise
+/// always detected as Chinese.
+fn detect_language(text: &str, expected: &[&'static Language]) -> Option<Detection> {
+    let is_expected = |code| expected.iter().any(|language| language.code == code);
+    let info = detect_among(text, expected.iter().copied()).or_else(|| {
+        let supported = LANGUAGES
+            .iter()
+            .filter(|language| language.code != "zh" || !is_expected("ja"));
+        detect_among(text, supported)
+    })?;
+
+    Some(Detection {
+        language: Language::from_whatlang(info.lang())?,
+        confidence: info.confidence(),
+    })
+}
+
+fn detect_among<'a>(
+    text: &str,
+    languages: impl Iterator<Item = &'a Language>,
+) -> Option<whatlang::Info> {
+    Detector::with_allowlist(languages.map(|language| language.whatlang).collect()).detect(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translator::fake::Fake;
+    use crate::translator::Error;
+
+    fn origin() -> Origin {
+        Origin {
+            user_id: UserId::new(1),
+            guild_id: None,
+            channel_id: ChannelId::new(1),
+        }
+    }
+
+    fn language(code: &str) -> &'static Language {
+        Language::from_code(code).unwrap()
+    }
+
+    #[tokio::test]
+    async fn translates_around_markup() {
+        let data = Data::for_tests(Box::<Fake>::default());
+        let settings = data.default_settings.clone();
+        let text = "**Hello** <@123>, how are you?";
+        let translation = Translation::from_text(
+            &data,
+            text,
+            origin(),
+            Some(language("en")),
+            language("ja"),
+            &settings,
+        )
+        .await
+        .unwrap();
+
+        let translated = translation.translate(language("ja")).await.unwrap();
+        assert_eq!(translated.content, format!("[en→ja] {}", text));
+    }
+
+    #[tokio::test]
+    async fn bills_the_text_without_markup() {
+        let data = Data::for_tests(Box::<Fake>::default());
+        let settings = data.default_settings.clone();
+        let guild_id = GuildId::new(1);
+        let translation = Translation::from_text(
+            &data,
+            "**Hello** <@123> there",
+            Origin {
+                guild_id: Some(guild_id),
+                ..origin()
+            },
+            Some(language("en")),
+            language("ja"),
+            &settings,
+        )
+        .await
+        .unwrap();
+
+        translation.translate(language("ja")).await.unwrap();
+        assert_eq!(
+            data.database.monthly_usage(guild_id).unwrap(),
+            "Hello   there".len() as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn pauses_when_the_quota_is_used_up() {
+        let data = Data::for_tests(Box::new(Fake {
+            fail: Some(|| Error::QuotaExceeded),
+        }));
+        let settings = data.default_settings.clone();
+        let translation = Translation::from_text(
+            &data,
+            "Hello, how are you?",
+            origin(),
+            Some(language("en")),
+            language("ja"),
+            &settings,
+        )
+        .await
+        .unwrap();
+
+        let result = translation.translate(language("ja")).await;
+        assert!(matches!(
+            result,
+            Err(BotError::Translator(Error::QuotaExceeded))
+        ));
+        assert!(data.quota_exceeded());
+    }
+
+    #[tokio::test]
+    async fn asks_the_translator_for_unknown_scripts() {
+        let data = Data::for_tests(Box::<Fake>::default());
+        let settings = data.default_settings.clone();
+        let translation =
+            Translation::from_text(&data, "ᚠᚢᚦᚨᚱᚲ", origin(), None, language("ja"), &settings)
+                .await
+                .unwrap();
+
+        assert_eq!(translation.detection.language.code, "en");
+    }
+
+    #[test]
+    fn quotes_replies_without_their_markup() {
+        let reply = |content: &str| {
+            let mut message = Message::default();
+            message.author.name = "[a*b]".to_string();
+            message.content = content.to_string();
+            Reply::new(&message)
+        };
+
+        assert_eq!(reply("hi").author, "\\[a\\*b\\]");
+        assert_eq!(
+            reply(&format!("**{}**", "a".repeat(70))).snippet,
+            format!("{}…", "a".repeat(MAX_SNIPPET_LENGTH))
+        );
+        assert_eq!(reply("see <@123> *here*\nmore").snippet, "see here…");
+        assert_eq!(reply("the ||ending|| is sad").snippet, "");
+    }
+
+    #[test]
+    fn detects_among_the_expected_languages() {
+        let detect = |text, codes: &[&str]| {
+            let expected = codes.iter().map(|code| language(code)).collect::<Vec<_>>();
+            detect_language(text, &expected).map(|detection| detection.language.code)
+        };
+
+        assert_eq!(detect("ok sounds good", &["en", "ja"]), Some("en"));
+        assert_eq!(detect("こんにちは、元気ですか", &["en", "ja"]), Some("ja"));
+        assert_eq!(
+            detect("¿Dónde está la biblioteca de la ciudad?", &["en", "es"]),
+            Some("es")
+        );
+        assert_eq!(
+            detect("Where is the library of the city?", &["en", "es"]),
+            Some("en")
+        );
+        assert_eq!(
+            detect("Wo ist die Bibliothek der Stadt?", &["en", "de"]),
+            Some("de")
+        );
+        // Not the script of any expected language
+        assert_eq!(detect("Привет, как дела?", &["en", "ja"]), Some("ru"));
+    }
 
-    if ascii_count > text.len() / 2 {
-        Lang::EN
-    } else {
-        Lang::JA
+    #[tokio::test]
+    async fn skips_text_without_letters() {
+        let data = Data::for_tests(Box::<Fake>::default());
+        let settings = data.default_settings.clone();
+        let result = Translation::from_text(
+            &data,
+            "<@123> :)",
+            origin(),
+            None,
+            language("ja"),
+            &settings,
+        )
+        .await;
+        assert!(matches!(result, Err(BotError::NothingToTranslate)));
     }
 }
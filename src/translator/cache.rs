@@ -0,0 +1,171 @@
+use super::{Error, Request, Response, Translator, Usage};
+use crate::database::Database;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::error;
+
+use poise::ChoiceParameter;
+
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+
+/// Translations by the hash of their request
+pub struct Cache {
+    memory: Mutex<LruCache<String, Response>>,
+    database: Option<Arc<Database>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    /// Keeps `size` translations in memory, and all of them in `database` if provided
+    pub fn new(size: usize, database: Option<Arc<Database>>) -> Self {
+        Self {
+            memory: Mutex::new(LruCache::new(
+                NonZeroUsize::new(size).unwrap_or(NonZeroUsize::MIN),
+            )),
+            database,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn get(&self, key: &str) -> Option<Response> {
+        let mut memory = self
+            .memory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(response) = memory.get(key) {
+            return Some(response.clone());
+        }
+
+        let response = self
+            .database
+            .as_ref()?
+            .cached_translation(key)
+            .unwrap_or_else(|err| {
+                error!("Failed to read the translation cache: {:#}", err);
+                None
+            })?;
+        memory.put(key.to_string(), response.clone());
+
+        Some(response)
+    }
+
+    fn put(&self, key: String, response: &Response) {
+        if let Some(database) = &self.database {
+            if let Err(err) = database.cache_translation(&key, response) {
+                error!("Failed to write the translation cache: {:#}", err);
+            }
+        }
+
+        self.memory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .put(key, response.clone());
+    }
+}
+
+/// A [`Translator`] that only asks `inner` for translations missing from `cache`
+pub struct Cached {
+    inner: Box<dyn Translator>,
+    cache: Arc<Cache>,
+}
+
+impl Cached {
+    pub fn new(inner: Box<dyn Translator>, cache: Arc<Cache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl Translator for Cached {
+    async fn translate(&self, request: &Request<'_>) -> Result<Response, Error> {
+        let key = cache_key(request);
+
+        if let Some(response) = self.cache.get(&key) {
+            self.cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Response {
+                billed_characters: 0,
+                ..response
+            });
+        }
+        self.cache.misses.fetch_add(1, Ordering::Relaxed);
+
+        let response = self.inner.translate(request).await?;
+        self.cache.put(key, &response);
+
+        Ok(response)
+    }
+
+    async fn detect(&self, text: &str) -> Result<String, Error> {
+        self.inner.detect(text).await
+    }
+
+    async fn usage(&self) -> Result<Usage> {
+        self.inner.usage().await
+    }
+
+    async fn supported_languages(&self) -> Result<Vec<String>> {
+        self.inner.supported_languages().await
+    }
+
+    async fn create_glossary(
+        &self,
+        name: &str,
+        source: &str,
+        target: &str,
+        entries: &[(String, String)],
+    ) -> Result<String> {
+        self.inner
+            .create_glossary(name, source, target, entries)
+            .await
+    }
+
+    async fn delete_glossary(&self, id: &str) -> Result<()> {
+        self.inner.delete_glossary(id).await
+    }
+}
+
+/// Hashes everything that can change the outcome of `request`
+fn cache_key(request: &Request<'_>) -> String {
+    let mut hasher = Sha256::new();
+
+    for field in [
+        request.text,
+        request.source.unwrap_or(""),
+        request.target,
+        if request.xml { "xml" } else { "" },
+        request.glossary.unwrap_or(""),
+        request.context.unwrap_or(""),
+        request
+            .options
+            .formality
+            .map_or("", |formality| formality.name()),
+        request
+            .options
+            .split_sentences
+            .map_or("", |split| split.name()),
+        request
+            .options
+            .preserve_formatting
+            .map_or("", |preserve| if preserve { "1" } else { "0" }),
+    ] {
+        hasher.update(field.len().to_le_bytes());
+        hasher.update(field);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
@@ -0,0 +1,249 @@
+use crate::metrics;
+
+use super::{retry, Error, Formality, Request, Response, SplitSentences, Translator, Usage};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use deepl::reqwest::{Client, StatusCode, Url};
+use deepl::{DeepLApi, Lang, LangType, TranslateTextResp};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+
+/// Target languages that support formality, others fail with [`Formality::More`] and
+/// [`Formality::Less`]
+const FORMALITY_LANGUAGES: &[&str] = &["de", "es", "fr", "it", "ja", "nl", "pl", "pt", "ru"];
+
+pub struct DeepL {
+    api: DeepLApi,
+    client: Client,
+    auth_key: SecretString,
+    endpoint: Url,
+}
+
+impl DeepL {
+    pub fn new(auth_key: &SecretString) -> Self {
+        let client = Client::new();
+        let endpoint = match auth_key.expose_secret().ends_with(":fx") {
+            true => "https://api-free.deepl.com/v2/",
+            false => "https://api.deepl.com/v2/",
+        };
+
+        Self {
+            api: DeepLApi::with(auth_key.expose_secret())
+                .client(client.clone())
+                .new(),
+            client,
+            auth_key: auth_key.clone(),
+            endpoint: Url::parse(endpoint).unwrap(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl DeepL {
+    async fn send(&self, form: &[(&str, String)]) -> Result<TranslateTextResp, Error> {
+        let fail = |class: &str, err: Error| {
+            metrics::DEEPL_ERRORS.with_label_values(&[class]).inc();
+            err
+        };
+
+        let url = self
+            .endpoint
+            .join("translate")
+            .map_err(|err| Error::Other(err.to_string()))?;
+        let timer = metrics::DEEPL_DURATION.start_timer();
+        let response = self
+            .client
+            .post(url)
+            .header(
+                "Authorization",
+                format!("DeepL-Auth-Key {}", self.auth_key.expose_secret()),
+            )
+            .form(form)
+            .send()
+            .await
+            .map_err(|err| fail("network", Error::Network(err.to_string())))?;
+        timer.observe_duration();
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response
+                .json::<ErrorResponse>()
+                .await
+                .map(|error| error.message)
+                .unwrap_or_else(|_| status.to_string());
+            return Err(fail(status.as_str(), status_error(status, message)));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|err| fail("invalid_response", Error::Other(err.to_string())))
+    }
+}
+
+#[async_trait]
+impl Translator for DeepL {
+    /// Sent without [`DeepLApi`], which doesn't expose sentence splitting and formatting
+    async fn translate(&self, request: &Request<'_>) -> Result<Response, Error> {
+        let supported =
+            |code: &str| to_lang(code).map_err(|_| Error::UnsupportedLanguage(code.to_string()));
+        let mut form = vec![
+            ("text", request.text.to_string()),
+            (
+                "target_lang",
+                supported(request.target)?.as_ref().to_string(),
+            ),
+        ];
+        if let Some(source) = request.source {
+            form.push(("source_lang", supported(source)?.as_ref().to_string()));
+        }
+        if request.xml {
+            form.push(("tag_handling", "xml".to_string()));
+        }
+        if let Some(glossary) = request.glossary {
+            form.push(("glossary_id", glossary.to_string()));
+        }
+        if let Some(context) = request.context {
+            form.push(("context", context.to_string()));
+        }
+
+        let options = &request.options;
+        if let Some(formality) = options.formality {
+            let formality = match formality {
+                _ if FORMALITY_LANGUAGES.contains(&request.target) => formality,
+                Formality::More => Formality::PreferMore,
+                Formality::Less => Formality::PreferLess,
+                formality => formality,
+            };
+            form.push(("formality", formality_param(formality).to_string()));
+        }
+        if let Some(split_sentences) = options.split_sentences {
+            let split_sentences = match split_sentences {
+                SplitSentences::Default => "1",
+                SplitSentences::NoNewlines => "nonewlines",
+                SplitSentences::Off => "0",
+            };
+            form.push(("split_sentences", split_sentences.to_string()));
+        }
+        if let Some(preserve_formatting) = options.preserve_formatting {
+            form.push((
+                "preserve_formatting",
+                (preserve_formatting as u8).to_string(),
+            ));
+        }
+
+        let translated = retry(|| self.send(&form)).await?;
+
+        Ok(Response {
+            text: translated.to_string(),
+            billed_characters: request.characters,
+        })
+    }
+
+    async fn detect(&self, text: &str) -> Result<String, Error> {
+        let form = [
+            ("text", text.to_string()),
+            ("target_lang", Lang::EN.as_ref().to_string()),
+        ];
+        let translated = retry(|| self.send(&form)).await?;
+        let sentence = translated
+            .translations
+            .first()
+            .ok_or_else(|| Error::Other("DeepL returned no translations".to_string()))?;
+
+        Ok(sentence.detected_source_language.as_ref().to_lowercase())
+    }
+
+    async fn usage(&self) -> Result<Usage> {
+        let usage = self.api.get_usage().await?;
+
+        Ok(Usage {
+            character_count: usage.character_count,
+            character_limit: usage.character_limit,
+        })
+    }
+
+    /// The source languages, which are all target languages too, as `EN` rather than `EN-GB`
+    async fn supported_languages(&self) -> Result<Vec<String>> {
+        let languages = self.api.languages(LangType::Source).await?;
+
+        Ok(languages
+            .into_iter()
+            .map(|info| info.language.to_lowercase())
+            .collect())
+    }
+
+    async fn create_glossary(
+        &self,
+        name: &str,
+        source: &str,
+        target: &str,
+        entries: &[(String, String)],
+    ) -> Result<String> {
+        let glossary = self
+            .api
+            .create_glossary(name)
+            .source_lang(to_lang(source)?)
+            .target_lang(to_lang(target)?)
+            .entries(entries)
+            .send()
+            .await?;
+
+        Ok(glossary.glossary_id)
+    }
+
+    async fn delete_glossary(&self, id: &str) -> Result<()> {
+        self.api.delete_glossary(id).await?;
+        Ok(())
+    }
+}
+
+/// The error DeepL means with `status`, see <https://developers.deepl.com/docs/best-practices/error-handling>
+fn status_error(status: StatusCode, message: String) -> Error {
+    match status.as_u16() {
+        401 | 403 => Error::Auth,
+        413 | 414 => Error::TextTooLong,
+        429 => Error::RateLimited,
+        456 => Error::QuotaExceeded,
+        500..=599 => Error::Unavailable,
+        _ => Error::Other(message),
+    }
+}
+
+fn to_lang(code: &str) -> Result<Lang> {
+    Ok(Lang::try_from(code.to_uppercase().as_str())?)
+}
+
+fn formality_param(formality: Formality) -> deepl::Formality {
+    match formality {
+        Formality::Default => deepl::Formality::Default,
+        Formality::More => deepl::Formality::More,
+        Formality::Less => deepl::Formality::Less,
+        Formality::PreferMore => deepl::Formality::PreferMore,
+        Formality::PreferLess => deepl::Formality::PreferLess,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors_by_status() {
+        let classify = |status| status_error(StatusCode::from_u16(status).unwrap(), String::new());
+
+        assert!(matches!(classify(456), Error::QuotaExceeded));
+        assert!(matches!(classify(429), Error::RateLimited));
+        assert!(matches!(classify(401), Error::Auth));
+        assert!(matches!(classify(403), Error::Auth));
+        assert!(matches!(classify(413), Error::TextTooLong));
+        assert!(matches!(classify(503), Error::Unavailable));
+        assert!(matches!(classify(400), Error::Other(_)));
+    }
+}
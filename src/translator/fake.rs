@@ -0,0 +1,69 @@
+use super::{Error, Request, Response, Translator, Usage};
+use crate::language::LANGUAGES;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// A [`Translator`] that tags text with the languages instead of translating it
+///
+/// `Hello` from `en` into `ja` becomes `[en→ja] Hello`, `[?→ja] Hello` without a source.
+#[derive(Default)]
+pub struct Fake {
+    /// Makes every translation fail with the error it returns
+    pub fail: Option<fn() -> Error>,
+}
+
+#[async_trait]
+impl Translator for Fake {
+    async fn translate(&self, request: &Request<'_>) -> Result<Response, Error> {
+        if let Some(fail) = self.fail {
+            return Err(fail());
+        }
+
+        Ok(Response {
+            text: format!(
+                "[{}→{}] {}",
+                request.source.unwrap_or("?"),
+                request.target,
+                request.text
+            ),
+            billed_characters: request.characters,
+        })
+    }
+
+    /// Detects everything as English
+    async fn detect(&self, _text: &str) -> Result<String, Error> {
+        match self.fail {
+            Some(fail) => Err(fail()),
+            None => Ok("en".to_string()),
+        }
+    }
+
+    async fn usage(&self) -> Result<Usage> {
+        Ok(Usage {
+            character_count: 0,
+            character_limit: u64::MAX,
+        })
+    }
+
+    async fn supported_languages(&self) -> Result<Vec<String>> {
+        Ok(LANGUAGES
+            .iter()
+            .map(|language| language.code.to_string())
+            .collect())
+    }
+
+    async fn create_glossary(
+        &self,
+        name: &str,
+        _source: &str,
+        _target: &str,
+        _entries: &[(String, String)],
+    ) -> Result<String> {
+        Ok(name.to_string())
+    }
+
+    async fn delete_glossary(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+pub mod cache;
+pub mod deepl;
+#[cfg(test)]
+pub mod fake;
+
+use crate::Config;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt::{self, Display};
+use std::future::Future;
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+
+use poise::ChoiceParameter;
+
+use serde::Deserialize;
+
+/// Attempts at a request failing with retryable errors
+const MAX_ATTEMPTS: u32 = 4;
+/// Wait after the first failed attempt, doubled after each one
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A translation provider
+///
+/// Languages are identified by lowercase ISO 639-1 codes (`en`, `ja`, `pt-br`, ...),
+/// implementations are responsible for mapping them to whatever the provider expects.
+#[async_trait]
+pub trait Translator: Send + Sync {
+    async fn translate(&self, request: &Request<'_>) -> Result<Response, Error>;
+
+    /// Detects the language of `text`, this costs as much as translating it
+    async fn detect(&self, text: &str) -> Result<String, Error>;
+
+    async fn usage(&self) -> Result<Usage>;
+
+    /// Codes of the languages that can be translated from and into
+    async fn supported_languages(&self) -> Result<Vec<String>>;
+
+    /// Creates a glossary, returning its id
+    async fn create_glossary(
+        &self,
+        name: &str,
+        source: &str,
+        target: &str,
+        entries: &[(String, String)],
+    ) -> Result<String>;
+
+    async fn delete_glossary(&self, id: &str) -> Result<()>;
+}
+
+pub struct Request<'a> {
+    pub text: &'a str,
+    /// Whether `text` is XML, whose tags are kept in place
+    pub xml: bool,
+    /// Characters of `text` without its markup, the ones billed for it
+    pub characters: u64,
+    /// Required when using a glossary
+    pub source: Option<&'a str>,
+    pub target: &'a str,
+    pub glossary: Option<&'a str>,
+    /// Text around `text` that influences its translation without being translated
+    pub context: Option<&'a str>,
+    pub options: Options,
+}
+
+/// How text is translated, unset options are left to the translator
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Options {
+    pub formality: Option<Formality>,
+    pub split_sentences: Option<SplitSentences>,
+    pub preserve_formatting: Option<bool>,
+}
+
+impl Options {
+    /// Fills the options unset in `self` with the ones of `fallback`
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            formality: self.formality.or(fallback.formality),
+            split_sentences: self.split_sentences.or(fallback.split_sentences),
+            preserve_formatting: self.preserve_formatting.or(fallback.preserve_formatting),
+        }
+    }
+}
+
+impl Display for Options {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Formality `{}`, sentence splitting `{}`, preserve formatting `{}`",
+            self.formality.map_or("unset", |formality| formality.name()),
+            self.split_sentences.map_or("unset", |split| split.name()),
+            self.preserve_formatting
+                .map_or("unset", |preserve| if preserve { "on" } else { "off" }),
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ChoiceParameter)]
+pub enum Formality {
+    #[name = "default"]
+    Default,
+    #[name = "more"]
+    More,
+    #[name = "less"]
+    Less,
+    /// Like [`Formality::More`], but ignored by languages without formality
+    #[name = "prefer_more"]
+    PreferMore,
+    /// Like [`Formality::Less`], but ignored by languages without formality
+    #[name = "prefer_less"]
+    PreferLess,
+}
+
+#[derive(Clone, Copy, PartialEq, ChoiceParameter)]
+pub enum SplitSentences {
+    /// On punctuation and new lines
+    #[name = "default"]
+    Default,
+    /// On punctuation only
+    #[name = "nonewlines"]
+    NoNewlines,
+    /// The whole text is translated as one sentence
+    #[name = "off"]
+    Off,
+}
+
+/// Why a translation failed
+#[derive(Debug)]
+pub enum Error {
+    /// The character limit of the billing period is used up
+    QuotaExceeded,
+    RateLimited,
+    /// The translator is down or overloaded
+    Unavailable,
+    /// The language code that isn't supported
+    UnsupportedLanguage(String),
+    TextTooLong,
+    Network(String),
+    /// The translator rejected the credentials
+    Auth,
+    Other(String),
+}
+
+impl Error {
+    /// Whether the request may succeed if sent again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Unavailable | Self::Network(_)
+        )
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QuotaExceeded => write!(f, "The translation quota is used up"),
+            Self::RateLimited => write!(f, "Too many translations at once, try again later"),
+            Self::Unavailable => write!(f, "The translator is unavailable, try again later"),
+            Self::UnsupportedLanguage(code) => {
+                write!(f, "The translator doesn't support `{}`", code)
+            }
+            Self::TextTooLong => write!(f, "The text is too long to translate"),
+            Self::Network(err) => write!(f, "Couldn't reach the translator: {}", err),
+            Self::Auth => write!(f, "The translator rejected the credentials"),
+            Self::Other(err) => write!(f, "Translation failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Runs `attempt` until it doesn't fail with a retryable error, waiting twice as long each time
+pub async fn retry<T, Fut>(attempt: impl Fn() -> Fut) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+
+    for _ in 1..MAX_ATTEMPTS {
+        match attempt().await {
+            Err(err) if err.is_retryable() => {
+                warn!("Retrying in {:?}: {}", backoff, err);
+                sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    attempt().await
+}
+
+#[derive(Clone)]
+pub struct Response {
+    pub text: String,
+    /// Characters counted against the translator's limit, `0` when cached
+    pub billed_characters: u64,
+}
+
+pub struct Usage {
+    pub character_count: u64,
+    pub character_limit: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    DeepL,
+}
+
+pub fn from_config(config: &Config) -> Box<dyn Translator> {
+    match config.translator {
+        Backend::DeepL => Box::new(deepl::DeepL::new(&config.deepl_auth_key)),
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{Data, Translation};
+use crate::error::BotError;
+use crate::language::Language;
+use crate::translation::Translated;
+use crate::{metrics, Data, Translation};
 
 use anyhow::{anyhow, Context as _, Error, Result};
 use tracing::error;
@@ -7,7 +10,8 @@ use poise::serenity_prelude as serenity;
 use poise::FrameworkContext;
 use serenity::{
     CacheHttp, Channel, ChannelId, ChannelType, CreateAllowedMentions, CreateAttachment,
-    CreateWebhook, EditWebhookMessage, ExecuteWebhook, Message, MessageFlags, User, Webhook,
+    CreateWebhook, EditWebhookMessage, ExecuteWebhook, Message, MessageFlags, MessageId, User,
+    Webhook,
 };
 
 #[derive(Clone)]
@@ -24,7 +28,7 @@ impl TranslateHook {
             .clone();
         let name = format!("auto-translate-{}", current_user.id);
 
-        let webhook_channel = get_parent(channel_id.to_channel(ctx).await?).unwrap_or(*channel_id);
+        let webhook_channel = webhook_channel(ctx, *channel_id).await?;
         let webhook = match webhook_channel
             .webhooks(ctx)
             .await?
@@ -50,6 +54,28 @@ impl TranslateHook {
         framework: &FrameworkContext<'_, Data, Error>,
         message: &Message,
         translation: &Translation<'_>,
+        target: &Language,
+    ) -> Result<Message> {
+        self.post(
+            ctx,
+            framework,
+            message,
+            message.channel_id,
+            translation,
+            target,
+        )
+        .await
+    }
+
+    /// Posts the translation of `message` in `channel_id` under the name and avatar of its author
+    pub async fn post(
+        &self,
+        ctx: &impl CacheHttp,
+        framework: &FrameworkContext<'_, Data, Error>,
+        message: &Message,
+        channel_id: ChannelId,
+        translation: &Translation<'_>,
+        target: &Language,
     ) -> Result<Message> {
         let avatar_url = avatar_url(&message.author);
 
@@ -57,26 +83,39 @@ impl TranslateHook {
             let author = message.author.clone();
             author.global_name.unwrap_or(author.name)
         });
-        let username = format!("{} {}", username, translation.target.flag);
+        let username = format!("{} {}", username, target.flag);
 
+        // Translated embeds can't be shown once suppressed
+        let flags = match translation.has_embeds() {
+            true => MessageFlags::empty(),
+            false => MessageFlags::SUPPRESS_EMBEDS,
+        };
         let builder = ExecuteWebhook::new()
             .avatar_url(avatar_url)
             .username(username)
             .content(format!("{}\u{200b}", framework.user_data.loading_emoji))
-            .flags(MessageFlags::SUPPRESS_EMBEDS)
+            .flags(flags)
             .allowed_mentions(CreateAllowedMentions::new());
-        let builder = match get_parent(message.channel(ctx).await?) {
-            Some(_) => builder.in_thread(message.channel_id),
+        let thread_id = self.thread_id(channel_id);
+        let builder = match thread_id {
+            Some(thread_id) => builder.in_thread(thread_id),
             None => builder,
         };
 
+        let timer = metrics::WEBHOOK_DURATION
+            .with_label_values(&["execute"])
+            .start_timer();
         let response = self
             .webhook
             .execute(ctx, true, builder)
-            .await?
+            .await
+            .map_err(BotError::from)?
             .ok_or_else(|| anyhow!("Webhook response is empty"))?;
+        timer.observe_duration();
 
-        let edited = self.edit_response(ctx, &response, translation).await?;
+        let edited = self
+            .edit_response(ctx, response.id, thread_id, translation, target)
+            .await?;
 
         Ok(edited)
     }
@@ -84,45 +123,76 @@ impl TranslateHook {
     pub async fn edit_response(
         &self,
         ctx: &impl CacheHttp,
-        message: &Message,
+        response_id: MessageId,
+        thread_id: Option<ChannelId>,
         translation: &Translation<'_>,
+        target: &Language,
     ) -> Result<Message> {
-        let builder = edit_builder_parent(
-            ctx,
-            message,
-            &translation
-                .translate()
-                .await
-                .unwrap_or_else(|| "Translation failed.".to_string()),
-        )
-        .await?;
+        let builder = match translation.translate(target).await {
+            Ok(translated) => translated_builder(translated, thread_id),
+            Err(err) => {
+                error!("Failed to translate into {}: {}", target.code, err);
+                edit_builder(&format!("{}.", err), thread_id)
+            }
+        };
 
-        let edit = |builder| self.webhook.edit_message(ctx, message.id, builder);
-        let response = match edit(builder).await {
+        let edit = |builder| async move {
+            let _timer = metrics::WEBHOOK_DURATION
+                .with_label_values(&["edit"])
+                .start_timer();
+            self.webhook.edit_message(ctx, response_id, builder).await
+        };
+        let response = match edit(builder).await.map_err(BotError::from) {
             Ok(edited) => edited,
             Err(err) => {
-                let error_message = if err.to_string() == "Message too large." {
-                    "Translation too long."
-                } else {
-                    error!("{}", err);
-                    "Translation failed."
+                let error_message = match err {
+                    BotError::TooLong => "Translation too long.",
+                    BotError::UnknownWebhook => return Err(err.into()),
+                    err => {
+                        error!("{}", err);
+                        "Translation failed."
+                    }
                 };
 
-                let builder = edit_builder_parent(ctx, message, error_message).await?;
-                edit(builder).await?
+                edit(edit_builder(error_message, thread_id))
+                    .await
+                    .map_err(BotError::from)?
             }
         };
 
         Ok(response)
     }
 
-    pub async fn delete(&self, ctx: &serenity::Context, message: &Message) -> Result<()> {
-        let thread_id = get_parent(message.channel(ctx).await?).map(|_| message.channel_id);
+    pub async fn delete(
+        &self,
+        ctx: &serenity::Context,
+        response_id: MessageId,
+        thread_id: Option<ChannelId>,
+    ) -> Result<()> {
         self.webhook
-            .delete_message(ctx, thread_id, message.id)
-            .await?;
+            .delete_message(ctx, thread_id, response_id)
+            .await
+            .map_err(BotError::from)?;
         Ok(())
     }
+
+    /// The thread a message in `channel_id` has to be sent to, if it isn't the webhook's channel
+    pub fn thread_id(&self, channel_id: ChannelId) -> Option<ChannelId> {
+        (self.webhook.channel_id != Some(channel_id)).then_some(channel_id)
+    }
+}
+
+/// The channel the webhook posting in `channel_id` belongs to, the parent of threads
+pub async fn webhook_channel(ctx: &serenity::Context, channel_id: ChannelId) -> Result<ChannelId> {
+    Ok(get_parent(channel_id.to_channel(ctx).await?).unwrap_or(channel_id))
+}
+
+/// Whether `err` is Discord's "Unknown Webhook", e.g. after someone deleted it
+pub fn is_unknown_webhook(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref::<BotError>(),
+        Some(BotError::UnknownWebhook)
+    )
 }
 
 fn avatar_url(user: &User) -> String {
@@ -142,16 +212,22 @@ fn get_parent(channel: Channel) -> Option<ChannelId> {
     None
 }
 
-async fn edit_builder_parent(
-    ctx: &impl CacheHttp,
-    message: &Message,
-    content: &str,
-) -> Result<EditWebhookMessage> {
+fn translated_builder(translated: Translated, thread_id: Option<ChannelId>) -> EditWebhookMessage {
+    translated.attachments.into_iter().fold(
+        edit_builder(&translated.content, thread_id).embeds(translated.embeds),
+        EditWebhookMessage::new_attachment,
+    )
+}
+
+/// Replaces the content, embeds and attachments of a response
+fn edit_builder(content: &str, thread_id: Option<ChannelId>) -> EditWebhookMessage {
     let builder = EditWebhookMessage::new()
         .content(content)
+        .embeds(Vec::new())
+        .clear_attachments()
         .allowed_mentions(CreateAllowedMentions::new());
-    match get_parent(message.channel(ctx).await?) {
-        Some(_) => Ok(builder.in_thread(message.channel_id)),
-        None => Ok(builder),
+    match thread_id {
+        Some(thread_id) => builder.in_thread(thread_id),
+        None => builder,
     }
 }